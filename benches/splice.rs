@@ -0,0 +1,104 @@
+//! Compare the userspace `io::copy` forwarding path with `splice(2)`.
+//!
+//! Run with `cargo bench --bench splice`. Set `MINGINX_BENCH_MB` to change
+//! how much data is pushed through the proxy per round.
+
+use std::time::{Duration, Instant};
+use _04_ecosystem::minginx::proxy;
+use anyhow::Result;
+use tokio::io::{self, AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+const ROUNDS: usize = 3;
+const CHUNK_SIZE: usize = 256 * 1024;
+
+#[derive(Debug, Clone, Copy)]
+enum Mode {
+    Copy,
+    Splice,
+}
+
+fn cpu_time() -> Duration {
+    let mut usage = unsafe { std::mem::zeroed::<libc::rusage>() };
+    unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) };
+    let to_duration =
+        |tv: libc::timeval| Duration::new(tv.tv_sec as u64, tv.tv_usec as u32 * 1000);
+    to_duration(usage.ru_utime) + to_duration(usage.ru_stime)
+}
+
+async fn sink() -> Result<String> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?.to_string();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let _ = io::copy(&mut stream, &mut io::sink()).await;
+            });
+        }
+    });
+    Ok(addr)
+}
+
+// 转发走 minginx 实际使用的 `proxy`，测的就是上线的代码
+async fn start_proxy(mode: Mode, upstream_addr: String) -> Result<String> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?.to_string();
+    tokio::spawn(async move {
+        while let Ok((client, _)) = listener.accept().await {
+            let upstream_addr = upstream_addr.clone();
+            tokio::spawn(async move {
+                let upstream = TcpStream::connect(upstream_addr).await?;
+                proxy(client, upstream, matches!(mode, Mode::Splice)).await
+            });
+        }
+    });
+    Ok(addr)
+}
+
+async fn round(proxy_addr: &str, total: usize) -> Result<()> {
+    let mut stream = TcpStream::connect(proxy_addr).await?;
+    let chunk = vec![0x5a; CHUNK_SIZE];
+    let mut sent = 0;
+    while sent < total {
+        let n = CHUNK_SIZE.min(total - sent);
+        stream.write_all(&chunk[..n]).await?;
+        sent += n;
+    }
+    stream.shutdown().await?;
+    // 等待上游关闭连接，确保数据已经全部经过代理
+    let mut buf = Vec::new();
+    stream.read_to_end(&mut buf).await?;
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let megabytes: usize = std::env::var("MINGINX_BENCH_MB")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(1024);
+    let total = megabytes * 1024 * 1024;
+    let upstream_addr = sink().await?;
+
+    for mode in [Mode::Copy, Mode::Splice] {
+        let proxy_addr = start_proxy(mode, upstream_addr.clone()).await?;
+        for i in 0..ROUNDS {
+            let cpu = cpu_time();
+            let start = Instant::now();
+            round(&proxy_addr, total).await?;
+            let elapsed = start.elapsed();
+            let cpu = cpu_time() - cpu;
+            println!(
+                "{:?} round {}: {} MiB in {:.3}s ({:.1} MiB/s), cpu {:.3}s",
+                mode,
+                i + 1,
+                megabytes,
+                elapsed.as_secs_f64(),
+                megabytes as f64 / elapsed.as_secs_f64(),
+                cpu.as_secs_f64(),
+            );
+        }
+    }
+
+    Ok(())
+}
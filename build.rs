@@ -0,0 +1,4 @@
+// sqlx::migrate! 会把 migrations 目录编译进去，修改其中的文件后需要重新编译
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+}
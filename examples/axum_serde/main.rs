@@ -0,0 +1,428 @@
+mod repo;
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use anyhow::Result;
+use axum::{async_trait, Json, Router};
+use axum::body::Bytes;
+use axum::extract::{FromRequest, Path, Request, State};
+use axum::http::header::{CONTENT_TYPE, ETAG, IF_MATCH};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use tokio::net::TcpListener;
+use tracing::{info, instrument, warn};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, Layer};
+use tracing_subscriber::prelude::__tracing_subscriber_SubscriberExt;
+use validator::{Validate, ValidationError, ValidationErrors};
+use crate::repo::UserRepo;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct User {
+    id: u64,
+    name: String,
+    age: u8,
+    skills: Vec<String>,
+}
+
+// PATCH 之后的文档也按它来解析，所以不接受多余的字段
+#[derive(Debug, Clone, Deserialize, Validate)]
+#[serde(deny_unknown_fields)]
+struct NewUser {
+    #[validate(length(min = 1, max = 64, message = "must be between 1 and 64 characters"))]
+    name: String,
+    #[validate(range(min = 1, max = 150, message = "must be between 1 and 150"))]
+    age: u8,
+    #[serde(default)]
+    #[validate(custom(function = "validate_skills"))]
+    skills: Vec<String>,
+}
+
+// 只修改请求里出现的字段，拼错的字段名要报错而不是被悄悄忽略
+#[derive(Debug, Clone, Deserialize, Validate)]
+#[serde(deny_unknown_fields)]
+struct UserUpdate {
+    #[validate(range(min = 1, max = 150, message = "must be between 1 and 150"))]
+    age: Option<u8>,
+    #[validate(custom(function = "validate_skills"))]
+    skills: Option<Vec<String>>,
+}
+
+/// Like `axum::Json`, but also runs the `Validate` rules, and reports every
+/// problem as a JSON body instead of plain text.
+#[derive(Debug)]
+struct ValidJson<T>(T);
+
+/// Body of `PATCH /users/:id`, chosen by `Content-Type`: `application/json`
+/// for a `UserUpdate`, or a JSON Merge Patch (RFC 7396) or JSON Patch
+/// (RFC 6902) document applied to the serialized user.
+#[derive(Debug)]
+enum UserPatch {
+    Update(UserUpdate),
+    Merge(Value),
+    Json(json_patch::Patch),
+}
+
+#[derive(Debug, Serialize)]
+struct FieldError {
+    // 出错字段的路径，比如 `skills[1]`，请求体整体有问题时为空
+    field: String,
+    message: String,
+}
+
+// 所有错误响应的格式
+#[derive(Debug, Serialize)]
+struct ApiError {
+    #[serde(skip)]
+    status: StatusCode,
+    message: String,
+    errors: Vec<FieldError>,
+}
+
+type AppState = Arc<dyn UserRepo>;
+
+impl NewUser {
+    fn into_user(self, id: u64) -> User {
+        User {
+            id,
+            name: self.name,
+            age: self.age,
+            skills: self.skills,
+        }
+    }
+}
+
+impl User {
+    // 内容的哈希，用户没有变化时 ETag 也不变
+    fn etag(&self) -> String {
+        let json = serde_json::to_vec(self).expect("User is always serializable");
+        format!("\"{}\"", &blake3::hash(&json).to_hex()[..16])
+    }
+
+    fn into_response_with_etag(self) -> Response {
+        ([(ETAG, self.etag())], Json(self)).into_response()
+    }
+
+    // 把打过补丁的文档重新解析并校验，id 不允许修改
+    fn from_patched(id: u64, mut doc: Value) -> Result<Self, ApiError> {
+        let doc_id = doc.as_object_mut().and_then(|obj| obj.remove("id"));
+        if doc_id != Some(Value::from(id)) {
+            let mut err = ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, "validation failed");
+            err.errors.push(FieldError {
+                field: "id".to_string(),
+                message: "cannot be changed".to_string(),
+            });
+            return Err(err);
+        }
+        let new_user: NewUser = deserialize(doc)?;
+        new_user.validate()?;
+        Ok(new_user.into_user(id))
+    }
+}
+
+/// Check `If-Match` against the current ETag of the user. Updates without
+/// the header are refused, so a client cannot overwrite a change it has not
+/// seen.
+fn check_if_match(headers: &HeaderMap, user: &User) -> Result<(), StatusCode> {
+    let value = headers
+        .get(IF_MATCH)
+        .ok_or(StatusCode::PRECONDITION_REQUIRED)?
+        .to_str()
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    let etag = user.etag();
+    if value.split(',').map(str::trim).any(|tag| tag == "*" || tag == etag) {
+        Ok(())
+    } else {
+        Err(StatusCode::PRECONDITION_FAILED)
+    }
+}
+
+fn validate_skills(skills: &[String]) -> Result<(), ValidationError> {
+    if skills.iter().any(|skill| skill.trim().is_empty()) {
+        return Err(ValidationError::new("empty_skill").with_message("skills must not be empty".into()));
+    }
+    let mut seen = HashSet::new();
+    if let Some(skill) = skills.iter().find(|skill| !seen.insert(skill.as_str())) {
+        let message = format!("skill {:?} is listed twice", skill);
+        return Err(ValidationError::new("duplicate_skill").with_message(message.into()));
+    }
+    Ok(())
+}
+
+impl ApiError {
+    fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+            errors: Vec::new(),
+        }
+    }
+
+    // 存储出错，具体原因只写日志
+    fn storage(err: anyhow::Error) -> Self {
+        warn!("storage error: {:#}", err);
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, "internal server error")
+    }
+}
+
+impl From<StatusCode> for ApiError {
+    fn from(status: StatusCode) -> Self {
+        Self::new(status, status.canonical_reason().unwrap_or_default())
+    }
+}
+
+impl From<json_patch::PatchError> for ApiError {
+    fn from(err: json_patch::PatchError) -> Self {
+        // test 操作失败说明用户已经不是客户端以为的样子
+        if matches!(err.kind, json_patch::PatchErrorKind::TestFailed) {
+            return Self::new(StatusCode::CONFLICT, err.to_string());
+        }
+        let mut body = Self::new(StatusCode::UNPROCESSABLE_ENTITY, "cannot apply patch");
+        body.errors.push(FieldError {
+            field: err.path.to_string(),
+            message: err.to_string(),
+        });
+        body
+    }
+}
+
+impl From<ValidationErrors> for ApiError {
+    fn from(errors: ValidationErrors) -> Self {
+        let mut body = Self::new(StatusCode::UNPROCESSABLE_ENTITY, "validation failed");
+        for (field, errors) in errors.field_errors() {
+            for error in errors {
+                let message = match &error.message {
+                    Some(message) => message.to_string(),
+                    None => error.code.to_string(),
+                };
+                body.errors.push(FieldError { field: field.to_string(), message });
+            }
+        }
+        // HashMap 的顺序不固定
+        body.errors.sort_by(|a, b| a.field.cmp(&b.field));
+        body
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(self)).into_response()
+    }
+}
+
+#[async_trait]
+impl<T, S> FromRequest<S> for ValidJson<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let is_json = media_type(req.headers())
+            .is_some_and(|v| v == "application/json" || v.ends_with("+json"));
+        if !is_json {
+            return Err(ApiError::new(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "expected a request with `Content-Type: application/json`",
+            ));
+        }
+        let value: T = from_slice(&body(req, state).await?)?;
+        value.validate()?;
+        Ok(ValidJson(value))
+    }
+}
+
+#[async_trait]
+impl<S> FromRequest<S> for UserPatch
+where
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        match media_type(req.headers()).as_deref() {
+            Some("application/merge-patch+json") => {
+                Ok(UserPatch::Merge(from_slice(&body(req, state).await?)?))
+            }
+            Some("application/json-patch+json") => {
+                Ok(UserPatch::Json(from_slice(&body(req, state).await?)?))
+            }
+            _ => {
+                let ValidJson(update) = ValidJson::from_request(req, state).await?;
+                Ok(UserPatch::Update(update))
+            }
+        }
+    }
+}
+
+impl UserPatch {
+    fn apply(self, user: &User) -> Result<User, ApiError> {
+        let doc = match self {
+            UserPatch::Update(update) => {
+                let mut user = user.clone();
+                update.apply(&mut user);
+                return Ok(user);
+            }
+            UserPatch::Merge(patch) => {
+                let mut doc = serde_json::to_value(user).expect("User is always serializable");
+                json_patch::merge(&mut doc, &patch);
+                doc
+            }
+            UserPatch::Json(patch) => {
+                let mut doc = serde_json::to_value(user).expect("User is always serializable");
+                json_patch::patch(&mut doc, &patch)?;
+                doc
+            }
+        };
+        User::from_patched(user.id, doc)
+    }
+}
+
+// 小写的 Content-Type，不含参数
+fn media_type(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .map(|v| v.trim().to_ascii_lowercase())
+}
+
+async fn body<S: Send + Sync>(req: Request, state: &S) -> Result<Bytes, ApiError> {
+    Bytes::from_request(req, state)
+        .await
+        .map_err(|e| ApiError::new(e.status(), e.body_text()))
+}
+
+fn from_slice<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, ApiError> {
+    deserialize(&mut serde_json::Deserializer::from_slice(bytes))
+}
+
+/// Deserialize with the path of the bad field, if any, in the error.
+fn deserialize<'de, D, T>(de: D) -> Result<T, ApiError>
+where
+    D: Deserializer<'de, Error = serde_json::Error>,
+    T: Deserialize<'de>,
+{
+    serde_path_to_error::deserialize(de).map_err(|e| {
+        let mut field = e.path().to_string();
+        if field == "." {
+            field.clear();
+        }
+        let inner = e.into_inner();
+        if inner.is_data() {
+            let mut body = ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, "invalid field");
+            body.errors.push(FieldError { field, message: inner.to_string() });
+            body
+        } else {
+            ApiError::new(StatusCode::BAD_REQUEST, format!("malformed JSON: {}", inner))
+        }
+    })
+}
+
+impl UserUpdate {
+    fn apply(self, user: &mut User) {
+        if let Some(age) = self.age {
+            user.age = age;
+        }
+
+        if let Some(skills) = self.skills {
+            user.skills = skills;
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let console = fmt::Layer::new()
+        .with_span_events(FmtSpan::CLOSE)
+        .pretty()
+        .with_filter(LevelFilter::DEBUG);
+
+    tracing_subscriber::registry().with(console).init();
+
+    // file:<目录> 或者 sqlite: 开头的地址
+    let url = std::env::var("USERS_DATABASE_URL").unwrap_or_else(|_| "file:users".to_string());
+    let state: AppState = repo::connect(&url).await?;
+    if state.list().await?.is_empty() {
+        state
+            .create(NewUser {
+                name: "Alice".to_string(),
+                age: 30,
+                skills: vec!["Rust".to_string(), "Python".to_string()],
+            })
+            .await?;
+    }
+
+    let addr = "0.0.0.0:8081";
+    let listener = TcpListener::bind(addr).await?;
+    info!("Listening on {}", addr);
+
+    let app = Router::new()
+        .route("/users", get(list_handler).post(create_handler))
+        .route(
+            "/users/:id",
+            get(user_handler).patch(update_handler).delete(delete_handler),
+        )
+        .with_state(state);
+
+    axum::serve(listener, app.into_make_service()).await?;
+    Ok(())
+}
+
+#[instrument(skip(state))]
+async fn list_handler(State(state): State<AppState>) -> Result<Json<Vec<User>>, ApiError> {
+    Ok(Json(state.list().await.map_err(ApiError::storage)?))
+}
+
+#[instrument(skip(state, new_user))]
+async fn create_handler(
+    State(state): State<AppState>,
+    ValidJson(new_user): ValidJson<NewUser>,
+) -> Result<(StatusCode, Response), ApiError> {
+    let user = state.create(new_user).await.map_err(ApiError::storage)?;
+    Ok((StatusCode::CREATED, user.into_response_with_etag()))
+}
+
+#[instrument(skip(state))]
+async fn user_handler(
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> Result<Response, ApiError> {
+    let user = state.get(id).await.map_err(ApiError::storage)?.ok_or(StatusCode::NOT_FOUND)?;
+    Ok(user.into_response_with_etag())
+}
+
+#[instrument(skip(state, headers, patch))]
+async fn update_handler(
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+    headers: HeaderMap,
+    patch: UserPatch,
+) -> Result<Response, ApiError> {
+    let user = state.get(id).await.map_err(ApiError::storage)?.ok_or(StatusCode::NOT_FOUND)?;
+    check_if_match(&headers, &user)?;
+    let updated = patch.apply(&user)?;
+    // 读取之后用户可能又被修改或删除了，这时 If-Match 已经不成立
+    if !state.replace(&user, &updated).await.map_err(ApiError::storage)? {
+        return Err(StatusCode::PRECONDITION_FAILED.into());
+    }
+    Ok(updated.into_response_with_etag())
+}
+
+#[instrument(skip(state))]
+async fn delete_handler(
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> Result<StatusCode, ApiError> {
+    match state.delete(id).await.map_err(ApiError::storage)? {
+        true => Ok(StatusCode::NO_CONTENT),
+        false => Err(StatusCode::NOT_FOUND.into()),
+    }
+}
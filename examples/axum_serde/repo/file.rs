@@ -0,0 +1,362 @@
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::fs::{self, File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+use crate::{NewUser, User};
+use super::UserRepo;
+
+const SNAPSHOT: &str = "snapshot.json";
+const WAL: &str = "wal.jsonl";
+// 日志超过这么多条就写一次快照并清空日志
+const COMPACT_AFTER: usize = 1000;
+
+/// Keeps every user in memory. Each change is appended to a write-ahead log
+/// and synced before it is applied, and the log is folded into a JSON
+/// snapshot on startup and every `COMPACT_AFTER` changes.
+#[derive(Debug)]
+pub struct FileRepo {
+    dir: PathBuf,
+    inner: Mutex<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    users: BTreeMap<u64, User>,
+    next_id: u64,
+    wal: File,
+    // 日志里有效内容的长度，以及上次尝试压缩之后追加的条数
+    wal_len: u64,
+    wal_entries: usize,
+    // 日志没能回滚，末尾可能留着半条记录，在重新打开之前不能再写
+    poisoned: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Snapshot {
+    // 最后分配的 id，删除的用户不会出现在 users 里
+    next_id: u64,
+    users: Vec<User>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+enum WalEntry {
+    Put { user: User },
+    Delete { id: u64 },
+}
+
+impl FileRepo {
+    pub async fn open(dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir).await?;
+
+        let snapshot: Snapshot = match fs::read(dir.join(SNAPSHOT)).await {
+            Ok(data) => serde_json::from_slice(&data)?,
+            Err(e) if e.kind() == ErrorKind::NotFound => Snapshot::default(),
+            Err(e) => return Err(e.into()),
+        };
+        let mut users: BTreeMap<u64, User> = snapshot.users.into_iter().map(|u| (u.id, u)).collect();
+        let mut next_id = snapshot.next_id;
+
+        let wal_path = dir.join(WAL);
+        let data = match fs::read(&wal_path).await {
+            Ok(data) => data,
+            Err(e) if e.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let mut replayed = 0;
+        for line in data.split(|b| *b == b'\n').filter(|line| !line.is_empty()) {
+            match serde_json::from_slice(line) {
+                Ok(entry) => {
+                    apply(&mut users, &mut next_id, entry);
+                    replayed += 1;
+                }
+                // 只有最后一行可能没写完，它对应的请求没有成功返回
+                Err(e) => {
+                    warn!("Ignoring a torn entry at the end of {}: {}", wal_path.display(), e);
+                    break;
+                }
+            }
+        }
+        info!("Loaded {} users from {}, replayed {} log entries", users.len(), dir.display(), replayed);
+
+        // 先写快照再清空日志，中途崩溃也不会丢数据
+        write_snapshot(&dir, &users, next_id).await?;
+        let wal = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&wal_path)
+            .await?;
+        wal.set_len(0).await?;
+        wal.sync_all().await?;
+
+        let inner = Inner {
+            users,
+            next_id,
+            wal,
+            wal_len: 0,
+            wal_entries: 0,
+            poisoned: false,
+        };
+        Ok(Self {
+            dir,
+            inner: Mutex::new(inner),
+        })
+    }
+
+    async fn commit(&self, inner: &mut Inner, entry: WalEntry) -> Result<()> {
+        if inner.poisoned {
+            bail!("{} could not be rolled back after a failed write, reopen the repo", WAL);
+        }
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+        let written = async {
+            inner.wal.write_all(&line).await?;
+            // tokio 的 File 在后台线程里写，写入的错误要 flush 才能拿到，sync_data 不会返回它
+            inner.wal.flush().await?;
+            inner.wal.sync_data().await
+        }
+        .await;
+        if let Err(e) = written {
+            // 去掉写了一半的内容，否则之后追加的记录在重放时会被忽略
+            if let Err(rollback) = inner.wal.set_len(inner.wal_len).await {
+                inner.poisoned = true;
+                return Err(anyhow!(rollback).context(format!("cannot roll back {} after a failed write: {}", WAL, e)));
+            }
+            return Err(e.into());
+        }
+        inner.wal_len += line.len() as u64;
+        inner.wal_entries += 1;
+        apply(&mut inner.users, &mut inner.next_id, entry);
+
+        if inner.wal_entries >= COMPACT_AFTER {
+            // 记录已经落盘并生效，压缩失败不能让这次写入报错，否则客户端重试会重复创建
+            if let Err(e) = self.compact(inner).await {
+                warn!("Failed to compact {}, retrying after {} more entries: {:#}", self.dir.display(), COMPACT_AFTER, e);
+                inner.wal_entries = 0;
+            }
+        }
+        Ok(())
+    }
+
+    async fn compact(&self, inner: &mut Inner) -> Result<()> {
+        write_snapshot(&self.dir, &inner.users, inner.next_id).await?;
+        inner.wal.set_len(0).await?;
+        inner.wal_len = 0;
+        inner.wal_entries = 0;
+        Ok(())
+    }
+}
+
+fn apply(users: &mut BTreeMap<u64, User>, next_id: &mut u64, entry: WalEntry) {
+    match entry {
+        WalEntry::Put { user } => {
+            *next_id = (*next_id).max(user.id);
+            users.insert(user.id, user);
+        }
+        WalEntry::Delete { id } => {
+            users.remove(&id);
+        }
+    }
+}
+
+// 写到临时文件再改名，读到的快照总是完整的
+async fn write_snapshot(dir: &Path, users: &BTreeMap<u64, User>, next_id: u64) -> Result<()> {
+    let snapshot = Snapshot {
+        next_id,
+        users: users.values().cloned().collect(),
+    };
+    let tmp = dir.join(format!("{}.tmp", SNAPSHOT));
+    let mut file = File::create(&tmp).await?;
+    file.write_all(&serde_json::to_vec(&snapshot)?).await?;
+    file.sync_all().await?;
+    fs::rename(&tmp, dir.join(SNAPSHOT)).await?;
+    // 改名本身也要落盘
+    File::open(dir).await?.sync_all().await?;
+    Ok(())
+}
+
+#[async_trait]
+impl UserRepo for FileRepo {
+    async fn list(&self) -> Result<Vec<User>> {
+        let inner = self.inner.lock().await;
+        Ok(inner.users.values().cloned().collect())
+    }
+
+    async fn get(&self, id: u64) -> Result<Option<User>> {
+        let inner = self.inner.lock().await;
+        Ok(inner.users.get(&id).cloned())
+    }
+
+    async fn create(&self, new_user: NewUser) -> Result<User> {
+        let mut inner = self.inner.lock().await;
+        let user = new_user.into_user(inner.next_id + 1);
+        self.commit(&mut inner, WalEntry::Put { user: user.clone() }).await?;
+        Ok(user)
+    }
+
+    async fn replace(&self, current: &User, new: &User) -> Result<bool> {
+        let mut inner = self.inner.lock().await;
+        if inner.users.get(&current.id) != Some(current) {
+            return Ok(false);
+        }
+        self.commit(&mut inner, WalEntry::Put { user: new.clone() }).await?;
+        Ok(true)
+    }
+
+    async fn delete(&self, id: u64) -> Result<bool> {
+        let mut inner = self.inner.lock().await;
+        if !inner.users.contains_key(&id) {
+            return Ok(false);
+        }
+        self.commit(&mut inner, WalEntry::Delete { id }).await?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 每个测试用自己的目录，上次运行留下的文件先删掉
+    async fn test_dir(name: &str) -> Result<PathBuf> {
+        let dir = std::env::temp_dir().join(format!("axum_serde-{}-{}", name, std::process::id()));
+        if let Err(e) = fs::remove_dir_all(&dir).await {
+            if e.kind() != ErrorKind::NotFound {
+                return Err(e.into());
+            }
+        }
+        Ok(dir)
+    }
+
+    fn new_user(name: &str) -> NewUser {
+        NewUser {
+            name: name.to_string(),
+            age: 30,
+            skills: vec!["Rust".to_string()],
+        }
+    }
+
+    #[tokio::test]
+    async fn replays_log_after_restart() -> Result<()> {
+        let dir = test_dir("replay").await?;
+        let repo = FileRepo::open(&dir).await?;
+        let alice = repo.create(new_user("alice")).await?;
+        let bob = repo.create(new_user("bob")).await?;
+        let older = User { age: 31, ..alice.clone() };
+        assert!(repo.replace(&alice, &older).await?);
+        assert!(repo.delete(bob.id).await?);
+        drop(repo);
+
+        let repo = FileRepo::open(&dir).await?;
+        assert_eq!(repo.list().await?, vec![older]);
+        // 删掉的 id 也不会再分配
+        assert_eq!(repo.create(new_user("carol")).await?.id, 3);
+        fs::remove_dir_all(&dir).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn ignores_torn_entry_at_end_of_log() -> Result<()> {
+        let dir = test_dir("torn").await?;
+        let repo = FileRepo::open(&dir).await?;
+        let alice = repo.create(new_user("alice")).await?;
+        drop(repo);
+
+        // 模拟写到一半时崩溃
+        let mut wal = OpenOptions::new().append(true).open(dir.join(WAL)).await?;
+        wal.write_all(br#"{"op":"put","user":{"id":2,"na"#).await?;
+        drop(wal);
+
+        let repo = FileRepo::open(&dir).await?;
+        assert_eq!(repo.list().await?, vec![alice.clone()]);
+        let bob = repo.create(new_user("bob")).await?;
+        assert_eq!(bob.id, 2);
+        drop(repo);
+
+        // 之后追加的记录不能因为那半条记录被丢掉
+        let repo = FileRepo::open(&dir).await?;
+        assert_eq!(repo.list().await?, vec![alice, bob]);
+        fs::remove_dir_all(&dir).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn compacts_log_into_snapshot() -> Result<()> {
+        let dir = test_dir("compact").await?;
+        let repo = FileRepo::open(&dir).await?;
+        for i in 0..COMPACT_AFTER {
+            repo.create(new_user(&format!("user{}", i))).await?;
+        }
+        assert_eq!(fs::metadata(dir.join(WAL)).await?.len(), 0);
+        let snapshot: Snapshot = serde_json::from_slice(&fs::read(dir.join(SNAPSHOT)).await?)?;
+        assert_eq!(snapshot.users.len(), COMPACT_AFTER);
+        assert_eq!(snapshot.next_id, COMPACT_AFTER as u64);
+
+        // 压缩之后的写入照常进日志
+        repo.create(new_user("last")).await?;
+        drop(repo);
+        let repo = FileRepo::open(&dir).await?;
+        assert_eq!(repo.list().await?.len(), COMPACT_AFTER + 1);
+        fs::remove_dir_all(&dir).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn keeps_write_when_compaction_fails() -> Result<()> {
+        let dir = test_dir("compact-fails").await?;
+        let repo = FileRepo::open(&dir).await?;
+        // 临时快照文件的位置被目录占住，写快照会失败
+        fs::create_dir(dir.join(format!("{}.tmp", SNAPSHOT))).await?;
+        for i in 0..COMPACT_AFTER {
+            repo.create(new_user(&format!("user{}", i))).await?;
+        }
+        assert_eq!(repo.list().await?.len(), COMPACT_AFTER);
+        drop(repo);
+
+        // 日志没有被清空，重新打开之后数据都在
+        fs::remove_dir(dir.join(format!("{}.tmp", SNAPSHOT))).await?;
+        let repo = FileRepo::open(&dir).await?;
+        assert_eq!(repo.list().await?.len(), COMPACT_AFTER);
+        fs::remove_dir_all(&dir).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reports_failed_append() -> Result<()> {
+        let dir = test_dir("append-fails").await?;
+        let repo = FileRepo::open(&dir).await?;
+        // 只读打开的文件写入会失败，fsync 却能成功
+        repo.inner.lock().await.wal = File::open(dir.join(WAL)).await?;
+
+        assert!(repo.create(new_user("alice")).await.is_err());
+        assert_eq!(repo.list().await?, Vec::new());
+        fs::remove_dir_all(&dir).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn poisoned_repo_refuses_writes() -> Result<()> {
+        let dir = test_dir("poisoned").await?;
+        let repo = FileRepo::open(&dir).await?;
+        let alice = repo.create(new_user("alice")).await?;
+        repo.inner.lock().await.poisoned = true;
+
+        assert!(repo.create(new_user("bob")).await.is_err());
+        assert!(repo.delete(alice.id).await.is_err());
+        assert_eq!(repo.list().await?, vec![alice.clone()]);
+        drop(repo);
+
+        // 重新打开之后恢复
+        let repo = FileRepo::open(&dir).await?;
+        assert!(repo.delete(alice.id).await?);
+        fs::remove_dir_all(&dir).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,41 @@
+mod file;
+mod sqlite;
+
+use std::fmt::Debug;
+use std::sync::Arc;
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use crate::{NewUser, User};
+
+pub use file::FileRepo;
+pub use sqlite::SqliteRepo;
+
+/// Where users are kept. Ids are never reused, even after a delete.
+#[async_trait]
+pub trait UserRepo: Debug + Send + Sync {
+    async fn list(&self) -> Result<Vec<User>>;
+
+    async fn get(&self, id: u64) -> Result<Option<User>>;
+
+    async fn create(&self, new_user: NewUser) -> Result<User>;
+
+    /// Store `new` only if the user still equals `current`, so an update
+    /// based on a stale read is refused. Returns false when it was not
+    /// written.
+    async fn replace(&self, current: &User, new: &User) -> Result<bool>;
+
+    async fn delete(&self, id: u64) -> Result<bool>;
+}
+
+/// `file:<dir>` for a JSON snapshot plus write-ahead log in `dir`, or a
+/// `sqlite:` url.
+pub async fn connect(url: &str) -> Result<Arc<dyn UserRepo>> {
+    let repo: Arc<dyn UserRepo> = if let Some(dir) = url.strip_prefix("file:") {
+        Arc::new(FileRepo::open(dir).await?)
+    } else if url.starts_with("sqlite:") {
+        Arc::new(SqliteRepo::try_new(url).await?)
+    } else {
+        bail!("unsupported database url: {}", url);
+    };
+    Ok(repo)
+}
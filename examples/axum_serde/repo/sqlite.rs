@@ -0,0 +1,143 @@
+use std::str::FromStr;
+use anyhow::Result;
+use async_trait::async_trait;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use sqlx::SqlitePool;
+use crate::{NewUser, User};
+use super::UserRepo;
+
+// id, name, age, skills，skills 存成 JSON 数组
+type UserRow = (i64, String, i64, String);
+
+#[derive(Debug, Clone)]
+pub struct SqliteRepo {
+    db: SqlitePool,
+}
+
+impl SqliteRepo {
+    pub async fn try_new(url: &str) -> Result<Self> {
+        let options = SqliteConnectOptions::from_str(url)?.create_if_missing(true);
+        // 内存数据库每个连接都是独立的一份，只能用一个连接，并且不能让它被回收
+        let pool = if url.contains(":memory:") {
+            SqlitePoolOptions::new()
+                .max_connections(1)
+                .idle_timeout(None)
+                .max_lifetime(None)
+        } else {
+            SqlitePoolOptions::new()
+        };
+        let db = pool.connect_with(options).await?;
+        // AUTOINCREMENT 保证删除后 id 不会被重新分配
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS users (id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT NOT NULL, age INTEGER NOT NULL, skills TEXT NOT NULL)",
+        ).execute(&db)
+            .await?;
+        Ok(Self { db })
+    }
+}
+
+fn from_row((id, name, age, skills): UserRow) -> Result<User> {
+    Ok(User {
+        id: id.try_into()?,
+        name,
+        age: age.try_into()?,
+        skills: serde_json::from_str(&skills)?,
+    })
+}
+
+#[async_trait]
+impl UserRepo for SqliteRepo {
+    async fn list(&self) -> Result<Vec<User>> {
+        let rows = sqlx::query_as::<_, UserRow>("SELECT id, name, age, skills FROM users ORDER BY id")
+            .fetch_all(&self.db)
+            .await?;
+        rows.into_iter().map(from_row).collect()
+    }
+
+    async fn get(&self, id: u64) -> Result<Option<User>> {
+        let row = sqlx::query_as::<_, UserRow>("SELECT id, name, age, skills FROM users WHERE id = ?")
+            .bind(id as i64)
+            .fetch_optional(&self.db)
+            .await?;
+        row.map(from_row).transpose()
+    }
+
+    async fn create(&self, new_user: NewUser) -> Result<User> {
+        let id = sqlx::query_scalar::<_, i64>(
+            "INSERT INTO users (name, age, skills) VALUES (?, ?, ?) RETURNING id",
+        ).bind(&new_user.name)
+            .bind(new_user.age as i64)
+            .bind(serde_json::to_string(&new_user.skills)?)
+            .fetch_one(&self.db)
+            .await?;
+        Ok(new_user.into_user(id.try_into()?))
+    }
+
+    async fn replace(&self, current: &User, new: &User) -> Result<bool> {
+        // 所有字段都没变才更新，相当于比较 ETag
+        let ret = sqlx::query(
+            "UPDATE users SET name = ?, age = ?, skills = ? WHERE id = ? AND name = ? AND age = ? AND skills = ?",
+        ).bind(&new.name)
+            .bind(new.age as i64)
+            .bind(serde_json::to_string(&new.skills)?)
+            .bind(current.id as i64)
+            .bind(&current.name)
+            .bind(current.age as i64)
+            .bind(serde_json::to_string(&current.skills)?)
+            .execute(&self.db)
+            .await?;
+        Ok(ret.rows_affected() == 1)
+    }
+
+    async fn delete(&self, id: u64) -> Result<bool> {
+        let ret = sqlx::query("DELETE FROM users WHERE id = ?")
+            .bind(id as i64)
+            .execute(&self.db)
+            .await?;
+        Ok(ret.rows_affected() == 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_user(name: &str) -> NewUser {
+        NewUser {
+            name: name.to_string(),
+            age: 30,
+            skills: vec!["Rust".to_string(), "SQL".to_string()],
+        }
+    }
+
+    #[tokio::test]
+    async fn stores_users() -> Result<()> {
+        let repo = SqliteRepo::try_new("sqlite::memory:").await?;
+        let alice = repo.create(new_user("alice")).await?;
+        let bob = repo.create(new_user("bob")).await?;
+        assert_eq!(repo.get(alice.id).await?, Some(alice.clone()));
+        assert_eq!(repo.list().await?, vec![alice.clone(), bob.clone()]);
+
+        assert!(repo.delete(bob.id).await?);
+        assert!(!repo.delete(bob.id).await?);
+        assert_eq!(repo.get(bob.id).await?, None);
+        // 删掉的 id 不会再分配
+        assert_eq!(repo.create(new_user("carol")).await?.id, 3);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn replace_refuses_stale_user() -> Result<()> {
+        let repo = SqliteRepo::try_new("sqlite::memory:").await?;
+        let alice = repo.create(new_user("alice")).await?;
+        let older = User { age: 31, ..alice.clone() };
+        assert!(repo.replace(&alice, &older).await?);
+        assert_eq!(repo.get(alice.id).await?, Some(older.clone()));
+
+        // 基于旧版本的修改不能覆盖
+        let renamed = User { name: "alicia".to_string(), ..alice.clone() };
+        assert!(!repo.replace(&alice, &renamed).await?);
+        assert_eq!(repo.get(alice.id).await?, Some(older));
+        Ok(())
+    }
+}
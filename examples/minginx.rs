@@ -1,42 +1,48 @@
 use std::sync::Arc;
+use _04_ecosystem::minginx::{serve, Config, Listener, ListenerConfig};
 use anyhow::Result;
-use serde::{Deserialize, Serialize};
-use tokio::net::{TcpListener, TcpStream};
-use tokio::io;
+use tokio::signal::unix::{signal, SignalKind};
 use tracing::{info, level_filters::LevelFilter, warn};
 use tracing_subscriber::fmt::Layer;
 use tracing_subscriber::Layer as _;
 use tracing_subscriber::prelude::__tracing_subscriber_SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 
-#[derive(Clone, Deserialize, Serialize)]
-struct Config {
-    upstream_addr: String,
-    listen_addr: String,
-}
-
-
-
-fn resolve_config() -> Config {
-    Config {
-        upstream_addr: "0.0.0.0:8080".to_string(),
-        listen_addr: "0.0.0.0:8081".to_string(),
+fn resolve_config() -> Result<Config> {
+    if let Ok(path) = std::env::var("MINGINX_CONFIG") {
+        let content = std::fs::read_to_string(&path)?;
+        return Ok(serde_json::from_str(&content)?);
     }
-}
 
+    Ok(Config {
+        listeners: vec![ListenerConfig::new(
+            "0.0.0.0:8081",
+            vec!["0.0.0.0:8080".to_string()],
+        )],
+    })
+}
 
-async fn proxy(mut client: TcpStream, mut upstream: TcpStream) -> Result<()> {
-    let (mut client_reader, mut client_writer) = client.split();
-    let (mut upstream_reader, mut upstream_writer) = upstream.split();
-    let client_to_upstream = io::copy(&mut client_reader, &mut upstream_writer);
-    let upstream_to_client = io::copy(&mut upstream_reader, &mut client_writer);
-    match tokio::try_join!(client_to_upstream, upstream_to_client) {
-        Ok((n,m)) => {
-            info!("proxied {} bytes from client to upstream, {} bytes from upstream to client", n, m);
-        },
-        Err(e) => {warn!("error proxying: {:?}", e)}
+async fn reload_access_lists(listeners: Vec<Arc<Listener>>) -> Result<()> {
+    let mut hangup = signal(SignalKind::hangup())?;
+    while hangup.recv().await.is_some() {
+        let config = match resolve_config() {
+            Ok(config) => config,
+            Err(e) => {
+                warn!("failed to reload config: {:?}", e);
+                continue;
+            }
+        };
+        for listener in &listeners {
+            let new = config
+                .listeners
+                .iter()
+                .find(|l| l.listen_addr == listener.config().listen_addr);
+            if let Some(new) = new {
+                listener.set_access(new.access.clone());
+                info!("Reloaded access lists for {}: {:?}", new.listen_addr, new.access);
+            }
+        }
     }
-
     Ok(())
 }
 
@@ -45,25 +51,21 @@ async fn main() -> Result<()> {
 
     let layer = Layer::new().with_filter(LevelFilter::INFO);
     tracing_subscriber::registry().with(layer).init();
-    let config = resolve_config();
-    let config = Arc::new(config);
-    info!("Upstream is {}", config.upstream_addr);
-    info!("Listening on {}", config.listen_addr);
-    let listener = TcpListener::bind(&config.listen_addr).await?;
-    loop {
-        let (client, addr) = listener.accept().await?;
-        info!("Accepted connection from {}", addr);
-        let cloned_config = config.clone();
-
-        tokio::spawn(async move {
-            let upstream = TcpStream::connect(&cloned_config.upstream_addr).await?;
+    let config = resolve_config()?;
+    let mut listeners = Vec::new();
+    for listener in config.listeners {
+        listener.validate()?;
+        listeners.push(Arc::new(Listener::new(listener)));
+    }
+    tokio::spawn(reload_access_lists(listeners.clone()));
 
-            // 将client代理到上游
-            proxy(client, upstream).await?;
-            Ok::<_, anyhow::Error>(())
-        });
+    let mut servers = Vec::new();
+    for listener in listeners {
+        servers.push(tokio::spawn(serve(listener)));
+    }
+    for server in servers {
+        server.await??;
     }
 
-    #[allow(unreachable_code)]
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,19 @@
+-- 之前的版本在启动时直接建表，这里兼容那些已经存在的表：
+-- 最早的 id 是 CHAR(6)，url 全局唯一
+CREATE TABLE IF NOT EXISTS urls (
+    id VARCHAR(64) PRIMARY KEY,
+    url TEXT NOT NULL,
+    custom BOOLEAN NOT NULL DEFAULT FALSE
+);
+ALTER TABLE urls ALTER COLUMN id TYPE VARCHAR(64);
+ALTER TABLE urls ADD COLUMN IF NOT EXISTS custom BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE urls DROP CONSTRAINT IF EXISTS urls_url_key;
+-- custom 的链接（别名、带过期设置的链接）允许多个 id 指向同一个 url
+CREATE UNIQUE INDEX IF NOT EXISTS urls_generated_url ON urls (url) WHERE NOT custom;
+ALTER TABLE urls ADD COLUMN IF NOT EXISTS expires_at TIMESTAMPTZ;
+ALTER TABLE urls ADD COLUMN IF NOT EXISTS max_visits BIGINT;
+ALTER TABLE urls ADD COLUMN IF NOT EXISTS visits BIGINT NOT NULL DEFAULT 0;
+CREATE INDEX IF NOT EXISTS urls_expires_at ON urls (expires_at) WHERE expires_at IS NOT NULL;
+ALTER TABLE urls ADD COLUMN IF NOT EXISTS redirect_status INTEGER;
+ALTER TABLE urls ADD COLUMN IF NOT EXISTS owner TEXT;
+CREATE INDEX IF NOT EXISTS urls_owner ON urls (owner, id) WHERE owner IS NOT NULL;
@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS urls (
+    id VARCHAR(64) PRIMARY KEY,
+    url TEXT NOT NULL,
+    -- custom 的链接（别名、带过期设置的链接）允许多个 id 指向同一个 url
+    custom BOOLEAN NOT NULL DEFAULT FALSE,
+    expires_at TIMESTAMP,
+    max_visits INTEGER,
+    visits INTEGER NOT NULL DEFAULT 0,
+    redirect_status INTEGER,
+    owner TEXT
+);
+CREATE UNIQUE INDEX IF NOT EXISTS urls_generated_url ON urls (url) WHERE NOT custom;
+CREATE INDEX IF NOT EXISTS urls_expires_at ON urls (expires_at) WHERE expires_at IS NOT NULL;
+CREATE INDEX IF NOT EXISTS urls_owner ON urls (owner, id) WHERE owner IS NOT NULL;
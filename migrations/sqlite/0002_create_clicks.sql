@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS clicks (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    link_id VARCHAR(64) NOT NULL,
+    clicked_at TIMESTAMP NOT NULL,
+    referrer TEXT,
+    user_agent TEXT,
+    ip_hash VARCHAR(64)
+);
+CREATE INDEX IF NOT EXISTS clicks_link_id ON clicks (link_id, clicked_at);
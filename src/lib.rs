@@ -1,3 +1,5 @@
 mod error;
+pub mod minginx;
+pub mod shortener;
 
 pub use error::MyError;
\ No newline at end of file
@@ -0,0 +1,127 @@
+use std::net::IpAddr;
+use anyhow::{bail, Result};
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+use super::ProxyProtocol;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Config {
+    pub listeners: Vec<ListenerConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ListenerConfig {
+    pub listen_addr: String,
+    pub upstream_addrs: Vec<String>,
+    #[serde(default)]
+    pub protocol: Protocol,
+    #[serde(default)]
+    pub balance: Balance,
+    // UDP 会话在多长时间没有报文后过期
+    #[serde(default = "default_udp_session_timeout")]
+    pub udp_session_timeout_secs: u64,
+    // 同时存在的 UDP 会话上限，达到后丢弃新客户端的报文
+    #[serde(default = "default_max_udp_sessions")]
+    pub max_udp_sessions: usize,
+    // minginx 前面还有一层负载均衡时，从连接开头解析 PROXY 头
+    #[serde(default)]
+    pub accept_proxy_protocol: bool,
+    // 等待 PROXY 头的最长时间，超时就关闭连接，防止慢速攻击占住连接
+    #[serde(default = "default_proxy_header_timeout")]
+    pub proxy_header_timeout_secs: u64,
+    // 向上游发送 PROXY 头，让上游拿到真实的客户端地址
+    #[serde(default)]
+    pub send_proxy_protocol: Option<ProxyProtocol>,
+    // Linux 上用 splice(2) 在内核里直接转发，适合大流量传输
+    #[serde(default)]
+    pub zero_copy: bool,
+    #[serde(default)]
+    pub access: AccessControl,
+}
+
+// deny 优先；allow 为空时允许所有未被 deny 的地址
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct AccessControl {
+    #[serde(default)]
+    pub allow: Vec<IpNet>,
+    #[serde(default)]
+    pub deny: Vec<IpNet>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    #[default]
+    Tcp,
+    Udp,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Balance {
+    #[default]
+    RoundRobin,
+    // 同一个客户端 IP 总是落到同一个上游
+    SourceHash,
+}
+
+fn default_udp_session_timeout() -> u64 {
+    30
+}
+
+fn default_max_udp_sessions() -> usize {
+    4096
+}
+
+fn default_proxy_header_timeout() -> u64 {
+    5
+}
+
+impl ListenerConfig {
+    /// A plain TCP listener forwarding to `upstream_addrs` with every
+    /// optional feature turned off.
+    pub fn new(listen_addr: impl Into<String>, upstream_addrs: Vec<String>) -> Self {
+        Self {
+            listen_addr: listen_addr.into(),
+            upstream_addrs,
+            protocol: Protocol::Tcp,
+            balance: Balance::RoundRobin,
+            udp_session_timeout_secs: default_udp_session_timeout(),
+            max_udp_sessions: default_max_udp_sessions(),
+            accept_proxy_protocol: false,
+            proxy_header_timeout_secs: default_proxy_header_timeout(),
+            send_proxy_protocol: None,
+            zero_copy: false,
+            access: AccessControl::default(),
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.upstream_addrs.is_empty() {
+            bail!("listener {} has no upstreams", self.listen_addr);
+        }
+        if self.protocol == Protocol::Udp
+            && (self.accept_proxy_protocol || self.send_proxy_protocol.is_some())
+        {
+            bail!("listener {}: PROXY protocol is only supported for tcp", self.listen_addr);
+        }
+        if self.protocol == Protocol::Udp && self.max_udp_sessions == 0 {
+            bail!("listener {}: max_udp_sessions must be at least 1", self.listen_addr);
+        }
+        if self.accept_proxy_protocol && self.proxy_header_timeout_secs == 0 {
+            bail!("listener {}: proxy_header_timeout_secs must be at least 1", self.listen_addr);
+        }
+        Ok(())
+    }
+}
+
+impl AccessControl {
+    pub fn is_allowed(&self, ip: IpAddr) -> bool {
+        // 双栈监听时 IPv4 客户端会以 ::ffff:a.b.c.d 的形式出现
+        let ip = ip.to_canonical();
+        if self.deny.iter().any(|net| net.contains(&ip)) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|net| net.contains(&ip))
+    }
+}
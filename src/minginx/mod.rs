@@ -0,0 +1,164 @@
+mod config;
+mod proxy_protocol;
+mod splice;
+mod udp;
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use anyhow::{bail, Result};
+use bytes::BytesMut;
+use tokio::io::{self, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tracing::{info, warn};
+
+pub use config::{AccessControl, Balance, Config, ListenerConfig, Protocol};
+pub use proxy_protocol::{parse_proxy_header, read_proxy_header, ProxyHeader, ProxyProtocol};
+pub use splice::splice_bidirectional;
+pub use udp::serve_udp;
+
+pub struct Listener {
+    config: ListenerConfig,
+    next_upstream: AtomicUsize,
+    // 收到 SIGHUP 时从配置文件重新加载
+    access: RwLock<AccessControl>,
+}
+
+impl Listener {
+    pub fn new(config: ListenerConfig) -> Self {
+        Self {
+            access: RwLock::new(config.access.clone()),
+            config,
+            next_upstream: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn config(&self) -> &ListenerConfig {
+        &self.config
+    }
+
+    pub fn set_access(&self, access: AccessControl) {
+        *self.access.write().unwrap() = access;
+    }
+
+    fn is_allowed(&self, addr: SocketAddr) -> bool {
+        self.access.read().unwrap().is_allowed(addr.ip())
+    }
+
+    fn pick_upstream(&self, client: SocketAddr) -> &str {
+        &self.config.upstream_addrs[self.upstream_index(client)]
+    }
+
+    fn upstream_index(&self, client: SocketAddr) -> usize {
+        let idx = match self.config.balance {
+            Balance::RoundRobin => self.next_upstream.fetch_add(1, Ordering::Relaxed),
+            Balance::SourceHash => {
+                let mut hasher = DefaultHasher::new();
+                client.ip().hash(&mut hasher);
+                hasher.finish() as usize
+            }
+        };
+        idx % self.config.upstream_addrs.len()
+    }
+}
+
+/// Bind the listener's `listen_addr` and serve it until an error occurs.
+pub async fn serve(listener: Arc<Listener>) -> Result<()> {
+    info!("Upstreams are {:?}", listener.config.upstream_addrs);
+    info!("Listening on {} ({:?})", listener.config.listen_addr, listener.config.protocol);
+    match listener.config.protocol {
+        Protocol::Tcp => {
+            let tcp_listener = TcpListener::bind(&listener.config.listen_addr).await?;
+            serve_tcp(listener, tcp_listener).await
+        }
+        Protocol::Udp => {
+            let socket = UdpSocket::bind(&listener.config.listen_addr).await?;
+            serve_udp(listener, socket).await
+        }
+    }
+}
+
+/// Accept connections on an already bound `tcp_listener` and proxy each one
+/// to an upstream.
+pub async fn serve_tcp(listener: Arc<Listener>, tcp_listener: TcpListener) -> Result<()> {
+    loop {
+        let (client, addr) = tcp_listener.accept().await?;
+        if !listener.is_allowed(addr) {
+            warn!("Rejected connection from {} on {}", addr, listener.config.listen_addr);
+            continue;
+        }
+        info!("Accepted connection from {}", addr);
+        let cloned_listener = listener.clone();
+
+        tokio::spawn(async move {
+            if let Err(e) = handle(client, addr, cloned_listener).await {
+                warn!("connection from {} failed: {:?}", addr, e);
+            }
+        });
+    }
+}
+
+/// Copy data both ways until each side has sent EOF. The EOF is passed on
+/// to the other side so half-closed connections keep working.
+pub async fn proxy(mut client: TcpStream, mut upstream: TcpStream, zero_copy: bool) -> Result<()> {
+    let result = if zero_copy {
+        splice_bidirectional(&mut client, &mut upstream).await
+    } else {
+        let (mut client_reader, mut client_writer) = client.split();
+        let (mut upstream_reader, mut upstream_writer) = upstream.split();
+        let client_to_upstream = async {
+            let n = io::copy(&mut client_reader, &mut upstream_writer).await?;
+            upstream_writer.shutdown().await?;
+            Ok::<_, io::Error>(n)
+        };
+        let upstream_to_client = async {
+            let n = io::copy(&mut upstream_reader, &mut client_writer).await?;
+            client_writer.shutdown().await?;
+            Ok::<_, io::Error>(n)
+        };
+        tokio::try_join!(client_to_upstream, upstream_to_client)
+    };
+    match result {
+        Ok((n,m)) => {
+            info!("proxied {} bytes from client to upstream, {} bytes from upstream to client", n, m);
+        },
+        Err(e) => {warn!("error proxying: {:?}", e)}
+    }
+
+    Ok(())
+}
+
+async fn handle(mut client: TcpStream, addr: SocketAddr, listener: Arc<Listener>) -> Result<()> {
+    let config = &listener.config;
+    let mut header = ProxyHeader {
+        source: addr,
+        destination: client.local_addr()?,
+    };
+    let mut pending = BytesMut::new();
+    if config.accept_proxy_protocol {
+        let limit = Duration::from_secs(config.proxy_header_timeout_secs);
+        let (parsed, rest) = match tokio::time::timeout(limit, read_proxy_header(&mut client)).await {
+            Ok(ret) => ret?,
+            Err(_) => bail!("no complete PROXY header within {}s", config.proxy_header_timeout_secs),
+        };
+        if let Some(parsed) = parsed {
+            info!("{} is proxying for {}", addr, parsed.source);
+            header = parsed;
+        }
+        pending = rest;
+    }
+
+    let mut upstream = TcpStream::connect(listener.pick_upstream(header.source)).await?;
+    if let Some(version) = config.send_proxy_protocol {
+        upstream.write_all(&header.encode(version)).await?;
+    }
+    if !pending.is_empty() {
+        upstream.write_all(&pending).await?;
+    }
+
+    // 将client代理到上游
+    proxy(client, upstream, config.zero_copy).await
+}
@@ -0,0 +1,188 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use anyhow::{bail, Result};
+use bytes::{Buf, BufMut, BytesMut};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpStream;
+
+const PROXY_V1_PREFIX: &[u8] = b"PROXY ";
+// v1 头最长 107 字节（含 CRLF）
+const PROXY_V1_MAX_LEN: usize = 107;
+const PROXY_V2_SIGNATURE: &[u8; 12] = b"\r\n\r\n\0\r\nQUIT\n";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProxyProtocol {
+    V1,
+    V2,
+}
+
+/// Addresses carried by a PROXY header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProxyHeader {
+    pub source: SocketAddr,
+    pub destination: SocketAddr,
+}
+
+impl ProxyHeader {
+    pub fn encode(&self, version: ProxyProtocol) -> BytesMut {
+        // v1/v2 都要求源地址和目的地址同一个协议族
+        let (source, destination) = match (self.source, self.destination) {
+            (SocketAddr::V4(_), SocketAddr::V4(_)) | (SocketAddr::V6(_), SocketAddr::V6(_)) => {
+                (self.source, self.destination)
+            }
+            (source, destination) => (to_ipv6(source), to_ipv6(destination)),
+        };
+
+        match version {
+            ProxyProtocol::V1 => {
+                let family = if source.is_ipv4() { "TCP4" } else { "TCP6" };
+                let line = format!(
+                    "PROXY {} {} {} {} {}\r\n",
+                    family,
+                    source.ip(),
+                    destination.ip(),
+                    source.port(),
+                    destination.port()
+                );
+                BytesMut::from(line.as_bytes())
+            }
+            ProxyProtocol::V2 => {
+                let mut buf = BytesMut::with_capacity(16 + 36);
+                buf.put_slice(PROXY_V2_SIGNATURE);
+                // version 2, command PROXY
+                buf.put_u8(0x21);
+                match (source.ip(), destination.ip()) {
+                    (IpAddr::V4(src), IpAddr::V4(dst)) => {
+                        buf.put_u8(0x11);
+                        buf.put_u16(12);
+                        buf.put_slice(&src.octets());
+                        buf.put_slice(&dst.octets());
+                    }
+                    (IpAddr::V6(src), IpAddr::V6(dst)) => {
+                        buf.put_u8(0x21);
+                        buf.put_u16(36);
+                        buf.put_slice(&src.octets());
+                        buf.put_slice(&dst.octets());
+                    }
+                    _ => unreachable!("address families are normalized above"),
+                }
+                buf.put_u16(source.port());
+                buf.put_u16(destination.port());
+                buf
+            }
+        }
+    }
+}
+
+fn to_ipv6(addr: SocketAddr) -> SocketAddr {
+    match addr {
+        SocketAddr::V4(v4) => SocketAddr::new(IpAddr::V6(v4.ip().to_ipv6_mapped()), v4.port()),
+        v6 => v6,
+    }
+}
+
+/// Try to parse a PROXY header (v1 or v2) from the front of `buf`.
+///
+/// Returns `Ok(None)` when more bytes are needed, otherwise the parsed header
+/// and the number of bytes it occupied. The header is `None` for
+/// `UNKNOWN`/`LOCAL`, in which case the socket's own addresses apply.
+pub fn parse_proxy_header(buf: &[u8]) -> Result<Option<(Option<ProxyHeader>, usize)>> {
+    if buf.len() < PROXY_V2_SIGNATURE.len() {
+        let n = buf.len();
+        if PROXY_V2_SIGNATURE.starts_with(buf) || PROXY_V1_PREFIX.starts_with(&buf[..n.min(6)]) {
+            return Ok(None);
+        }
+        bail!("missing PROXY protocol header");
+    }
+
+    if buf.starts_with(PROXY_V2_SIGNATURE) {
+        parse_proxy_v2(buf)
+    } else if buf.starts_with(PROXY_V1_PREFIX) {
+        parse_proxy_v1(buf)
+    } else {
+        bail!("missing PROXY protocol header")
+    }
+}
+
+fn parse_proxy_v1(buf: &[u8]) -> Result<Option<(Option<ProxyHeader>, usize)>> {
+    let end = match buf.windows(2).position(|w| w == b"\r\n") {
+        Some(pos) => pos,
+        None if buf.len() >= PROXY_V1_MAX_LEN => bail!("PROXY v1 header too long"),
+        None => return Ok(None),
+    };
+    let line = std::str::from_utf8(&buf[..end])?;
+    let parts: Vec<&str> = line.split(' ').collect();
+    let header = match parts.as_slice() {
+        ["PROXY", "UNKNOWN", ..] => None,
+        ["PROXY", family @ ("TCP4" | "TCP6"), src, dst, sport, dport] => {
+            let src: IpAddr = src.parse()?;
+            let dst: IpAddr = dst.parse()?;
+            if src.is_ipv4() != (*family == "TCP4") || dst.is_ipv4() != (*family == "TCP4") {
+                bail!("PROXY v1 address does not match family {}", family);
+            }
+            Some(ProxyHeader {
+                source: SocketAddr::new(src, sport.parse()?),
+                destination: SocketAddr::new(dst, dport.parse()?),
+            })
+        }
+        _ => bail!("invalid PROXY v1 header: {:?}", line),
+    };
+    Ok(Some((header, end + 2)))
+}
+
+fn parse_proxy_v2(buf: &[u8]) -> Result<Option<(Option<ProxyHeader>, usize)>> {
+    if buf.len() < 16 {
+        return Ok(None);
+    }
+    let mut head = &buf[12..16];
+    let ver_cmd = head.get_u8();
+    let family = head.get_u8();
+    let len = head.get_u16() as usize;
+    if ver_cmd >> 4 != 2 {
+        bail!("unsupported PROXY protocol version {}", ver_cmd >> 4);
+    }
+    if buf.len() < 16 + len {
+        return Ok(None);
+    }
+    let mut body = &buf[16..16 + len];
+
+    let header = match (ver_cmd & 0x0f, family >> 4) {
+        // LOCAL 命令（健康检查等）以及不认识的协议族都直接使用连接本身的地址
+        (0x0, _) | (0x1, 0x0) => None,
+        (0x1, 0x1) if body.len() >= 12 => {
+            let src = Ipv4Addr::from(body.get_u32());
+            let dst = Ipv4Addr::from(body.get_u32());
+            Some(ProxyHeader {
+                source: SocketAddr::new(src.into(), body.get_u16()),
+                destination: SocketAddr::new(dst.into(), body.get_u16()),
+            })
+        }
+        (0x1, 0x2) if body.len() >= 36 => {
+            let src = Ipv6Addr::from(body.get_u128());
+            let dst = Ipv6Addr::from(body.get_u128());
+            Some(ProxyHeader {
+                source: SocketAddr::new(src.into(), body.get_u16()),
+                destination: SocketAddr::new(dst.into(), body.get_u16()),
+            })
+        }
+        (0x1, 0x3) => None,
+        (cmd, fam) => bail!("invalid PROXY v2 header: command {:#x}, family {:#x}", cmd, fam),
+    };
+    Ok(Some((header, 16 + len)))
+}
+
+/// Read a PROXY header from the client. Bytes read past the header are
+/// returned so they can be forwarded to the upstream.
+pub async fn read_proxy_header(client: &mut TcpStream) -> Result<(Option<ProxyHeader>, BytesMut)> {
+    let mut buf = BytesMut::with_capacity(256);
+    loop {
+        if let Some((header, len)) = parse_proxy_header(&buf)? {
+            buf.advance(len);
+            return Ok((header, buf));
+        }
+        if client.read_buf(&mut buf).await? == 0 {
+            bail!("connection closed before PROXY header was complete");
+        }
+    }
+}
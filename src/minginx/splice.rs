@@ -0,0 +1,169 @@
+use tokio::io;
+use tokio::net::TcpStream;
+
+/// Forward data in both directions between `a` and `b` until both sides
+/// reach EOF, returning the bytes copied `a -> b` and `b -> a`.
+///
+/// On Linux the bytes are moved with `splice(2)` through a kernel pipe so
+/// they never enter userspace. Elsewhere, or when the kernel refuses to
+/// splice these sockets, it falls back to a plain buffered copy.
+pub async fn splice_bidirectional(a: &mut TcpStream, b: &mut TcpStream) -> io::Result<(u64, u64)> {
+    #[cfg(target_os = "linux")]
+    {
+        let (a, b) = (&*a, &*b);
+        tokio::try_join!(linux::copy(a, b), linux::copy(b, a))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        io::copy_bidirectional(a, b).await
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+    use tokio::io::Interest;
+    use tokio::net::TcpStream;
+
+    // 每次最多搬运的字节数，和默认 pipe 缓冲区大小一致
+    const PIPE_SIZE: usize = 64 * 1024;
+
+    struct Pipe {
+        reader: OwnedFd,
+        writer: OwnedFd,
+    }
+
+    impl Pipe {
+        fn new() -> io::Result<Self> {
+            let mut fds = [0; 2];
+            let ret = unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK | libc::O_CLOEXEC) };
+            if ret < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(unsafe {
+                Self {
+                    reader: OwnedFd::from_raw_fd(fds[0]),
+                    writer: OwnedFd::from_raw_fd(fds[1]),
+                }
+            })
+        }
+    }
+
+    fn splice(from: RawFd, to: RawFd, len: usize) -> io::Result<usize> {
+        let ret = unsafe {
+            libc::splice(
+                from,
+                std::ptr::null_mut(),
+                to,
+                std::ptr::null_mut(),
+                len,
+                libc::SPLICE_F_MOVE | libc::SPLICE_F_NONBLOCK,
+            )
+        };
+        if ret < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(ret as usize)
+        }
+    }
+
+    fn is_unsupported(err: &io::Error) -> bool {
+        matches!(
+            err.raw_os_error(),
+            Some(libc::EINVAL | libc::ENOSYS | libc::EOPNOTSUPP)
+        )
+    }
+
+    fn shutdown_write(stream: &TcpStream) -> io::Result<()> {
+        let ret = unsafe { libc::shutdown(stream.as_raw_fd(), libc::SHUT_WR) };
+        if ret < 0 {
+            let err = io::Error::last_os_error();
+            // 对端已经关闭连接时不算错误
+            if err.kind() != io::ErrorKind::NotConnected {
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    /// Copy `src -> dst` until EOF, then half-close `dst`.
+    pub(super) async fn copy(src: &TcpStream, dst: &TcpStream) -> io::Result<u64> {
+        let n = match Pipe::new() {
+            Ok(pipe) => match splice_copy(src, dst, &pipe).await {
+                Err(SpliceError::Unsupported) => buffered_copy(src, dst).await?,
+                Err(SpliceError::Io(e)) => return Err(e),
+                Ok(n) => n,
+            },
+            Err(_) => buffered_copy(src, dst).await?,
+        };
+        shutdown_write(dst)?;
+        Ok(n)
+    }
+
+    enum SpliceError {
+        // splice 在第一次调用时就失败，还没有搬运任何数据，可以安全地回退
+        Unsupported,
+        Io(io::Error),
+    }
+
+    async fn splice_copy(src: &TcpStream, dst: &TcpStream, pipe: &Pipe) -> Result<u64, SpliceError> {
+        let mut total = 0u64;
+        loop {
+            let n = loop {
+                src.readable().await.map_err(SpliceError::Io)?;
+                match src.try_io(Interest::READABLE, || {
+                    splice(src.as_raw_fd(), pipe.writer.as_raw_fd(), PIPE_SIZE)
+                }) {
+                    Ok(n) => break n,
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                    Err(e) if total == 0 && is_unsupported(&e) => return Err(SpliceError::Unsupported),
+                    Err(e) => return Err(SpliceError::Io(e)),
+                }
+            };
+            if n == 0 {
+                return Ok(total);
+            }
+
+            // 把 pipe 里的数据全部写到 dst，保证下一轮 pipe 是空的
+            let mut pending = n;
+            while pending > 0 {
+                dst.writable().await.map_err(SpliceError::Io)?;
+                match dst.try_io(Interest::WRITABLE, || {
+                    splice(pipe.reader.as_raw_fd(), dst.as_raw_fd(), pending)
+                }) {
+                    Ok(m) => pending -= m,
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                    Err(e) => return Err(SpliceError::Io(e)),
+                }
+            }
+            total += n as u64;
+        }
+    }
+
+    async fn buffered_copy(src: &TcpStream, dst: &TcpStream) -> io::Result<u64> {
+        let mut buf = vec![0u8; PIPE_SIZE];
+        let mut total = 0u64;
+        loop {
+            src.readable().await?;
+            let n = match src.try_read(&mut buf) {
+                Ok(0) => return Ok(total),
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                Err(e) => return Err(e),
+            };
+
+            let mut written = 0;
+            while written < n {
+                dst.writable().await?;
+                match dst.try_write(&buf[written..n]) {
+                    Ok(m) => written += m,
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                    Err(e) => return Err(e),
+                }
+            }
+            total += n as u64;
+        }
+    }
+}
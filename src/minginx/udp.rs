@@ -0,0 +1,173 @@
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use anyhow::{anyhow, Result};
+use dashmap::DashMap;
+use tokio::net::UdpSocket;
+use tokio::sync::Notify;
+use tracing::{info, warn};
+use super::Listener;
+
+// 单个 UDP 报文的最大长度
+const MAX_DATAGRAM_SIZE: usize = 65535;
+// 丢弃报文的日志最多这么久打印一次，伪造源地址的报文不会刷屏
+const DROP_LOG_INTERVAL: Duration = Duration::from_secs(10);
+
+struct UdpSession {
+    upstream: Arc<UdpSocket>,
+    last_seen: Mutex<Instant>,
+    // 会话从表里移除时通知转发响应的任务退出，关闭上游 socket
+    closed: Notify,
+}
+
+// 记录丢弃了多少报文，决定什么时候打印日志
+#[derive(Default)]
+struct DropLog {
+    last: Option<Instant>,
+    dropped: u64,
+}
+
+impl DropLog {
+    // 需要打印时返回上次打印之后丢弃的报文数
+    fn hit(&mut self) -> Option<u64> {
+        self.dropped += 1;
+        if self.last.is_some_and(|last| last.elapsed() < DROP_LOG_INTERVAL) {
+            return None;
+        }
+        self.last = Some(Instant::now());
+        Some(std::mem::take(&mut self.dropped))
+    }
+}
+
+/// Forward datagrams received on `socket`. Each client address gets its own
+/// upstream socket so replies can be routed back to it.
+pub async fn serve_udp(listener: Arc<Listener>, socket: UdpSocket) -> Result<()> {
+    let socket = Arc::new(socket);
+    let sessions: Arc<DashMap<SocketAddr, Arc<UdpSession>>> = Arc::new(DashMap::new());
+    let idle_timeout = Duration::from_secs(listener.config.udp_session_timeout_secs);
+    let max_sessions = listener.config.max_udp_sessions;
+    // 上游地址只在启动时解析一次，收包循环里不做 DNS 查询
+    let mut upstreams = Vec::new();
+    for upstream in &listener.config.upstream_addrs {
+        let addr = tokio::net::lookup_host(upstream)
+            .await?
+            .next()
+            .ok_or_else(|| anyhow!("cannot resolve {}", upstream))?;
+        upstreams.push(addr);
+    }
+    let mut full = DropLog::default();
+    let mut denied = DropLog::default();
+    let mut buf = vec![0u8; MAX_DATAGRAM_SIZE];
+    loop {
+        let (n, addr) = socket.recv_from(&mut buf).await?;
+        // 每个报文都检查，重新加载访问列表后已有的会话也会被拒绝
+        if !listener.is_allowed(addr) {
+            if let Some((_, session)) = sessions.remove(&addr) {
+                session.closed.notify_one();
+                info!("Closed udp session from {}, it is no longer allowed", addr);
+            }
+            if let Some(n) = denied.hit() {
+                warn!("Rejected {} datagrams on {}, the last from {}", n, listener.config.listen_addr, addr);
+            }
+            continue;
+        }
+        let session = match sessions.get(&addr) {
+            Some(session) => session.clone(),
+            None => {
+                if sessions.len() >= max_sessions {
+                    if let Some(n) = full.hit() {
+                        warn!(
+                            "{} udp sessions open on {}, dropped {} datagrams from new clients such as {}",
+                            max_sessions, listener.config.listen_addr, n, addr
+                        );
+                    }
+                    continue;
+                }
+                let upstream_addr = upstreams[listener.upstream_index(addr)];
+                let session = match udp_session(upstream_addr) {
+                    Ok(session) => session,
+                    Err(e) => {
+                        warn!("failed to open udp session to {} for {}: {:?}", upstream_addr, addr, e);
+                        continue;
+                    }
+                };
+                info!("New udp session from {} to {}", addr, upstream_addr);
+                sessions.insert(addr, session.clone());
+                tokio::spawn(udp_reply(
+                    listener.clone(),
+                    socket.clone(),
+                    sessions.clone(),
+                    addr,
+                    session.clone(),
+                    idle_timeout,
+                ));
+                session
+            }
+        };
+
+        *session.last_seen.lock().unwrap() = Instant::now();
+        if let Err(e) = session.upstream.send(&buf[..n]).await {
+            warn!("failed to forward datagram from {}: {:?}", addr, e);
+        }
+    }
+}
+
+fn udp_session(upstream_addr: SocketAddr) -> io::Result<Arc<UdpSession>> {
+    let bind_addr: SocketAddr = if upstream_addr.is_ipv4() {
+        (Ipv4Addr::UNSPECIFIED, 0).into()
+    } else {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    };
+    // UDP 的 bind 和 connect 不会阻塞，直接用标准库的同步版本
+    let upstream = std::net::UdpSocket::bind(bind_addr)?;
+    upstream.connect(upstream_addr)?;
+    upstream.set_nonblocking(true)?;
+    Ok(Arc::new(UdpSession {
+        upstream: Arc::new(UdpSocket::from_std(upstream)?),
+        last_seen: Mutex::new(Instant::now()),
+        closed: Notify::new(),
+    }))
+}
+
+// 把上游的响应发回对应的客户端，会话空闲超时后清理
+async fn udp_reply(
+    listener: Arc<Listener>,
+    socket: Arc<UdpSocket>,
+    sessions: Arc<DashMap<SocketAddr, Arc<UdpSession>>>,
+    client: SocketAddr,
+    session: Arc<UdpSession>,
+    idle_timeout: Duration,
+) {
+    let mut buf = vec![0u8; MAX_DATAGRAM_SIZE];
+    loop {
+        // 只等到最后一次收发之后的 idle_timeout，而不是每次都重新等一整个周期
+        let deadline = *session.last_seen.lock().unwrap() + idle_timeout;
+        let received = tokio::select! {
+            _ = session.closed.notified() => break,
+            received = tokio::time::timeout_at(deadline.into(), session.upstream.recv(&mut buf)) => received,
+        };
+        match received {
+            Ok(Ok(_)) if !listener.is_allowed(client) => break,
+            Ok(Ok(n)) => {
+                *session.last_seen.lock().unwrap() = Instant::now();
+                if let Err(e) = socket.send_to(&buf[..n], client).await {
+                    warn!("failed to send datagram to {}: {:?}", client, e);
+                }
+            }
+            Ok(Err(e)) => {
+                warn!("udp upstream error for {}: {:?}", client, e);
+                break;
+            }
+            // 等待期间客户端可能又发了报文，这时 deadline 会往后推
+            Err(_) => {
+                if session.last_seen.lock().unwrap().elapsed() >= idle_timeout {
+                    break;
+                }
+            }
+        }
+    }
+    // 会话可能已经被移除，并且同一个客户端又建立了新的会话
+    sessions.remove_if(&client, |_, current| Arc::ptr_eq(current, &session));
+    info!("Closed udp session from {}", client);
+}
@@ -0,0 +1,132 @@
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Utc};
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::task::JoinHandle;
+use tracing::warn;
+use super::storage::{Click, Storage};
+use super::Metrics;
+
+// 每次最多写入多少条点击记录
+const BATCH_SIZE: usize = 256;
+// 过长的 referrer / user-agent 只保留前面这部分
+const MAX_HEADER_LEN: usize = 512;
+// 丢弃点击的日志最多这么久打印一次，过载时不会刷屏
+const DROP_LOG_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Queues clicks for a background task that writes them to storage in
+/// batches, so a redirect never waits on the database.
+#[derive(Debug, Clone)]
+pub struct ClickRecorder {
+    tx: mpsc::Sender<Click>,
+    salt: Arc<str>,
+    drop_log: Arc<Mutex<DropLog>>,
+}
+
+// 记录丢弃了多少点击，决定什么时候打印日志
+#[derive(Debug, Default)]
+struct DropLog {
+    last: Option<Instant>,
+    dropped: u64,
+}
+
+impl DropLog {
+    // 需要打印时返回上次打印之后丢弃的点击数
+    fn hit(&mut self) -> Option<u64> {
+        self.dropped += 1;
+        if self.last.is_some_and(|last| last.elapsed() < DROP_LOG_INTERVAL) {
+            return None;
+        }
+        self.last = Some(Instant::now());
+        Some(std::mem::take(&mut self.dropped))
+    }
+}
+
+impl ClickRecorder {
+    /// Spawn the writer task. It exits once every recorder has been dropped
+    /// and the queue is drained.
+    pub fn spawn(
+        store: Arc<dyn Storage>,
+        metrics: Arc<Metrics>,
+        buffer: usize,
+        salt: &str,
+    ) -> (Self, JoinHandle<()>) {
+        let (tx, rx) = mpsc::channel(buffer);
+        let handle = tokio::spawn(write_clicks(store, metrics, rx));
+        let recorder = Self {
+            tx,
+            salt: salt.into(),
+            drop_log: Arc::default(),
+        };
+        (recorder, handle)
+    }
+
+    pub fn click(
+        &self,
+        link_id: &str,
+        referrer: Option<&str>,
+        user_agent: Option<&str>,
+        ip: Option<IpAddr>,
+    ) -> Click {
+        Click {
+            link_id: link_id.to_string(),
+            clicked_at: Utc::now(),
+            referrer: referrer.map(truncate),
+            user_agent: user_agent.map(truncate),
+            ip_hash: ip.map(|ip| self.hash_ip(ip)),
+        }
+    }
+
+    /// Queue `click` without waiting. When the writer falls behind and the
+    /// queue is full the click is dropped and counted in the metrics.
+    pub fn record(&self, click: Click, metrics: &Metrics) {
+        match self.tx.try_send(click) {
+            Ok(()) => {}
+            Err(TrySendError::Full(click)) => {
+                Metrics::inc(&metrics.clicks_dropped);
+                if let Some(n) = self.drop_log.lock().unwrap().hit() {
+                    warn!("Click queue is full, dropped {} clicks, the last on {}", n, click.link_id);
+                }
+            }
+            Err(TrySendError::Closed(_)) => Metrics::inc(&metrics.clicks_dropped),
+        }
+    }
+
+    // 不保存原始 IP，只保存加盐后的哈希，用来区分不同的访问者
+    fn hash_ip(&self, ip: IpAddr) -> String {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(self.salt.as_bytes());
+        hasher.update(ip.to_string().as_bytes());
+        hasher.finalize().to_hex()[..16].to_string()
+    }
+}
+
+fn truncate(value: &str) -> String {
+    match value.char_indices().nth(MAX_HEADER_LEN) {
+        Some((idx, _)) => value[..idx].to_string(),
+        None => value.to_string(),
+    }
+}
+
+async fn write_clicks(store: Arc<dyn Storage>, metrics: Arc<Metrics>, mut rx: mpsc::Receiver<Click>) {
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    while rx.recv_many(&mut batch, BATCH_SIZE).await > 0 {
+        match store.record_clicks(&batch).await {
+            Ok(()) => {
+                Metrics::add(&metrics.clicks_recorded, batch.len() as u64);
+            }
+            Err(e) => {
+                Metrics::add(&metrics.clicks_dropped, batch.len() as u64);
+                warn!("Failed to record {} clicks: {}", batch.len(), e);
+            }
+        }
+        batch.clear();
+    }
+}
+
+/// Oldest day included in stats covering the last `days` days.
+pub(super) fn stats_since(now: DateTime<Utc>, days: u32) -> DateTime<Utc> {
+    let today = now.date_naive().and_hms_opt(0, 0, 0).unwrap().and_utc();
+    today - chrono::Duration::days(days.saturating_sub(1) as i64)
+}
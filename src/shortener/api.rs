@@ -0,0 +1,215 @@
+use axum::async_trait;
+use axum::body::{Body, Bytes};
+use axum::extract::rejection::{BytesRejection, JsonRejection, QueryRejection};
+use axum::extract::{DefaultBodyLimit, FromRequestParts, Path, Query, State};
+use axum::response::IntoResponse;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use chrono::{DateTime, Utc};
+use http::header::{AUTHORIZATION, CONTENT_TYPE};
+use http::request::Parts;
+use http::{HeaderMap, StatusCode};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+use super::{destination, AppError, AppState, Format, Link, RateLimitLayer, RedirectStatus};
+
+const MAX_PAGE_SIZE: usize = 500;
+
+/// Owner of the API key sent as `Authorization: Bearer <key>`.
+pub struct Owner(pub String);
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+struct ListQuery {
+    limit: usize,
+    // 上一页最后一个链接的 id
+    after: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ListRes {
+    links: Vec<LinkRes>,
+    // 没有下一页时为空
+    next: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct LinkRes {
+    id: String,
+    short_url: String,
+    url: String,
+    expires_at: Option<DateTime<Utc>>,
+    max_visits: Option<i64>,
+    visits: i64,
+    redirect_status: Option<RedirectStatus>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UpdateReq {
+    url: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FormatQuery {
+    format: Format,
+}
+
+impl Default for ListQuery {
+    fn default() -> Self {
+        Self { limit: 50, after: None }
+    }
+}
+
+impl AppState {
+    /// Owner of the API key in `headers`, `None` when no key was sent.
+    pub(super) fn authenticate(&self, headers: &HeaderMap) -> Result<Option<String>, AppError> {
+        let Some(value) = headers.get(AUTHORIZATION) else {
+            return Ok(None);
+        };
+        let key = value
+            .to_str()
+            .ok()
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .ok_or(AppError::Unauthorized)?;
+        match self.config.api_keys.get(key.trim()) {
+            Some(owner) => Ok(Some(owner.clone())),
+            None => Err(AppError::Unauthorized),
+        }
+    }
+
+    fn link_res(&self, link: Link) -> LinkRes {
+        LinkRes {
+            short_url: format!("{}/{}", self.config.public_base_url(), link.id),
+            id: link.id,
+            url: link.url,
+            expires_at: link.expires_at,
+            max_visits: link.max_visits,
+            visits: link.visits,
+            redirect_status: link.redirect_status,
+        }
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<AppState> for Owner {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        match state.authenticate(&parts.headers)? {
+            Some(owner) => Ok(Owner(owner)),
+            None => Err(AppError::Unauthorized),
+        }
+    }
+}
+
+pub(super) fn routes(state: &AppState) -> Router<AppState> {
+    Router::new()
+        .route("/api/links", get(list_links))
+        // 导入也会创建链接，和 POST / 共用限流
+        .route(
+            "/api/links/import",
+            post(import_links).layer((
+                RateLimitLayer::new(state.clone()),
+                DefaultBodyLimit::max(state.config.max_import_bytes),
+            )),
+        )
+        .route("/api/links/export", get(export_links))
+        .route("/api/links/:id", get(get_link).patch(update_link).delete(delete_link))
+}
+
+async fn list_links(
+    Owner(owner): Owner,
+    State(state): State<AppState>,
+    query: Result<Query<ListQuery>, QueryRejection>,
+) -> Result<impl IntoResponse, AppError> {
+
+    let Query(query) = query?;
+    if !(1..=MAX_PAGE_SIZE).contains(&query.limit) {
+        return Err(AppError::InvalidInput(format!("limit must be between 1 and {}", MAX_PAGE_SIZE)));
+    }
+
+    let links = state
+        .store
+        .list(Some(&owner), query.after.as_deref(), query.limit)
+        .await
+        .map_err(AppError::storage)?;
+    let next = (links.len() == query.limit)
+        .then(|| links.last().map(|link| link.id.clone()))
+        .flatten();
+    let links = links.into_iter().map(|link| state.link_res(link)).collect();
+    Ok(Json(ListRes { links, next }))
+}
+
+async fn get_link(
+    Owner(owner): Owner,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let link = owned_link(&state, &id, &owner).await?;
+    Ok(Json(state.link_res(link)))
+}
+
+async fn update_link(
+    Owner(owner): Owner,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    data: Result<Json<UpdateReq>, JsonRejection>,
+) -> Result<impl IntoResponse, AppError> {
+
+    let Json(data) = data?;
+    destination::check_url(&state.config, &data.url).map_err(AppError::InvalidInput)?;
+
+    let updated = state
+        .store
+        .update_url(&id, &owner, &data.url)
+        .await
+        .map_err(AppError::storage)?;
+    if !updated {
+        return Err(AppError::NotFound);
+    }
+    let link = owned_link(&state, &id, &owner).await?;
+    Ok(Json(state.link_res(link)))
+}
+
+async fn delete_link(
+    Owner(owner): Owner,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let deleted = state.store.delete(&id, &owner).await.map_err(AppError::storage)?;
+    if !deleted {
+        return Err(AppError::NotFound);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn import_links(
+    Owner(owner): Owner,
+    State(state): State<AppState>,
+    query: Result<Query<FormatQuery>, QueryRejection>,
+    body: Result<Bytes, BytesRejection>,
+) -> Result<impl IntoResponse, AppError> {
+    let Query(query) = query?;
+    let body = body?;
+    let report = state.import(&body, query.format, Some(&owner)).await;
+    info!("{} imported {} links, {} failed", owner, report.imported, report.failed);
+    Ok(Json(report))
+}
+
+async fn export_links(
+    Owner(owner): Owner,
+    State(state): State<AppState>,
+    query: Result<Query<FormatQuery>, QueryRejection>,
+) -> Result<impl IntoResponse, AppError> {
+    let Query(query) = query?;
+    let body = Body::from_stream(state.export(query.format, Some(owner)));
+    Ok(([(CONTENT_TYPE, query.format.content_type())], body))
+}
+
+// 别人的链接也返回 404，不暴露它是否存在
+async fn owned_link(state: &AppState, id: &str, owner: &str) -> Result<Link, AppError> {
+    let link = state.store.get(id).await.map_err(AppError::storage)?;
+    link.filter(|link| link.owner.as_deref() == Some(owner))
+        .ok_or(AppError::NotFound)
+}
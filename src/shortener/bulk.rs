@@ -0,0 +1,212 @@
+use std::str::FromStr;
+use anyhow::bail;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::Stream;
+use serde::{Deserialize, Serialize};
+use super::{AppError, AppState, Link, RedirectStatus, ShortenReq};
+
+// 导出时每次从数据库读取多少个链接
+const EXPORT_PAGE_SIZE: usize = 1000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Csv,
+    // 每行一个 JSON 对象
+    #[default]
+    Jsonl,
+}
+
+/// One link in an import or export. The same columns are used for CSV and
+/// JSON lines, so an export can be imported again.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LinkRecord {
+    // 为空时生成新的 id
+    pub id: Option<String>,
+    pub url: String,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub max_visits: Option<i64>,
+    pub redirect_status: Option<RedirectStatus>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ImportReport {
+    pub imported: usize,
+    pub failed: usize,
+    pub errors: Vec<RowError>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RowError {
+    pub line: u64,
+    pub id: Option<String>,
+    pub error: String,
+}
+
+impl Format {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Format::Csv => "text/csv",
+            Format::Jsonl => "application/x-ndjson",
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "csv" => Ok(Format::Csv),
+            "jsonl" | "ndjson" => Ok(Format::Jsonl),
+            _ => bail!("unknown format {:?}, expected csv or jsonl", s),
+        }
+    }
+}
+
+impl From<Link> for LinkRecord {
+    fn from(link: Link) -> Self {
+        Self {
+            id: Some(link.id),
+            url: link.url,
+            expires_at: link.expires_at,
+            max_visits: link.max_visits,
+            redirect_status: link.redirect_status,
+        }
+    }
+}
+
+/// Parse `data` into records, keeping the line each one started on. A row
+/// that cannot be parsed does not stop the rest.
+pub fn parse_records(data: &[u8], format: Format) -> Vec<(u64, Result<LinkRecord, String>)> {
+    match format {
+        Format::Csv => {
+            let mut reader = csv::Reader::from_reader(data);
+            let headers = match reader.headers() {
+                Ok(headers) => headers.clone(),
+                Err(e) => return vec![(1, Err(e.to_string()))],
+            };
+            let mut rows = Vec::new();
+            let mut record = csv::StringRecord::new();
+            loop {
+                match reader.read_record(&mut record) {
+                    Ok(false) => break,
+                    Ok(true) => {
+                        let line = record.position().map_or(0, |p| p.line());
+                        let row = record
+                            .deserialize(Some(&headers))
+                            .map_err(|e| e.to_string());
+                        rows.push((line, row));
+                    }
+                    Err(e) => {
+                        let line = e.position().map_or(0, |p| p.line());
+                        rows.push((line, Err(e.to_string())));
+                        // 格式错误之后的位置不可靠，不再继续
+                        if !matches!(e.kind(), csv::ErrorKind::UnequalLengths { .. }) {
+                            break;
+                        }
+                    }
+                }
+            }
+            rows
+        }
+        Format::Jsonl => data
+            .split(|b| *b == b'\n')
+            .enumerate()
+            .filter(|(_, line)| !line.trim_ascii().is_empty())
+            .map(|(idx, line)| {
+                let row = serde_json::from_slice(line).map_err(|e| e.to_string());
+                (idx as u64 + 1, row)
+            })
+            .collect(),
+    }
+}
+
+fn encode(records: &[LinkRecord], format: Format, with_headers: bool) -> anyhow::Result<Vec<u8>> {
+    match format {
+        Format::Csv => {
+            let mut writer = csv::WriterBuilder::new()
+                .has_headers(with_headers)
+                .from_writer(Vec::new());
+            for record in records {
+                writer.serialize(record)?;
+            }
+            Ok(writer.into_inner()?)
+        }
+        Format::Jsonl => {
+            let mut out = Vec::new();
+            for record in records {
+                serde_json::to_writer(&mut out, record)?;
+                out.push(b'\n');
+            }
+            Ok(out)
+        }
+    }
+}
+
+impl AppState {
+    /// Create a link for every record in `data`, with the same checks as
+    /// `POST /`. Records with an id keep it.
+    pub async fn import(&self, data: &[u8], format: Format, owner: Option<&str>) -> ImportReport {
+        let mut report = ImportReport::default();
+        for (line, row) in parse_records(data, format) {
+            let result = match &row {
+                Ok(record) => {
+                    let req = ShortenReq {
+                        url: record.url.clone(),
+                        alias: record.id.clone(),
+                        expires_at: record.expires_at,
+                        max_visits: record.max_visits,
+                        redirect_status: record.redirect_status,
+                        owner: owner.map(str::to_string),
+                    };
+                    self.create_link(&req).await.map(|_| ())
+                }
+                Err(e) => Err(AppError::InvalidInput(e.clone())),
+            };
+            match result {
+                Ok(()) => report.imported += 1,
+                Err(e) => {
+                    report.failed += 1;
+                    report.errors.push(RowError {
+                        line,
+                        id: row.ok().and_then(|record| record.id),
+                        error: e.message(),
+                    });
+                }
+            }
+        }
+        report
+    }
+
+    /// Stream every link, or only those of `owner`, page by page.
+    pub fn export(
+        &self,
+        format: Format,
+        owner: Option<String>,
+    ) -> impl Stream<Item = anyhow::Result<Bytes>> + Send + 'static {
+        let state = self.clone();
+        // None 表示已经导出完了
+        let start = Some((None::<String>, true));
+        futures::stream::try_unfold(start, move |cursor| {
+            let state = state.clone();
+            let owner = owner.clone();
+            async move {
+                let Some((after, first)) = cursor else {
+                    return Ok(None);
+                };
+                let links = state
+                    .store
+                    .list(owner.as_deref(), after.as_deref(), EXPORT_PAGE_SIZE)
+                    .await?;
+                let next = (links.len() == EXPORT_PAGE_SIZE)
+                    .then(|| links.last().map(|link| (Some(link.id.clone()), false)))
+                    .flatten();
+                let records: Vec<LinkRecord> = links.into_iter().map(Into::into).collect();
+                let chunk = encode(&records, format, first)?;
+                Ok(Some((Bytes::from(chunk), next)))
+            }
+        })
+    }
+}
@@ -0,0 +1,87 @@
+use std::net::{IpAddr, SocketAddr};
+use http::Uri;
+use super::Config;
+
+/// Check that `url` is something we are willing to redirect to: an absolute
+/// URL with an allowed scheme that does not point back at the shortener and
+/// whose host is not blocked.
+pub(super) fn check_url(config: &Config, url: &str) -> Result<(), String> {
+    if url.len() > config.max_url_length {
+        return Err(format!("url is longer than {} bytes", config.max_url_length));
+    }
+    let uri: Uri = url.parse().map_err(|e| format!("url is not valid: {}", e))?;
+    let Some(scheme) = uri.scheme_str() else {
+        return Err("url must be absolute".to_string());
+    };
+    if !config
+        .allowed_schemes
+        .iter()
+        .any(|allowed| allowed.eq_ignore_ascii_case(scheme))
+    {
+        return Err(format!("scheme {:?} is not allowed", scheme));
+    }
+    let Some(host) = uri.host().map(normalize_host).filter(|h| !h.is_empty()) else {
+        return Err("url must have a host".to_string());
+    };
+    let port = uri.port_u16().or_else(|| default_port(scheme));
+    if is_self(config, &host, port) {
+        return Err("url points back at the shortener".to_string());
+    }
+    if let Some(domain) = config
+        .blocked_domains
+        .iter()
+        .find(|domain| matches_domain(&host, domain))
+    {
+        return Err(format!("domain {:?} is blocked", domain));
+    }
+    Ok(())
+}
+
+// 去掉 IPv6 的方括号和末尾的点，统一成小写
+fn normalize_host(host: &str) -> String {
+    host.trim_start_matches('[')
+        .trim_end_matches(']')
+        .trim_end_matches('.')
+        .to_ascii_lowercase()
+}
+
+fn default_port(scheme: &str) -> Option<u16> {
+    match scheme.to_ascii_lowercase().as_str() {
+        "http" => Some(80),
+        "https" => Some(443),
+        _ => None,
+    }
+}
+
+// 域名本身以及它的所有子域名
+fn matches_domain(host: &str, domain: &str) -> bool {
+    let domain = normalize_host(domain);
+    host == domain || host.strip_suffix(&domain).is_some_and(|rest| rest.ends_with('.'))
+}
+
+fn is_self(config: &Config, host: &str, port: Option<u16>) -> bool {
+    // 对外的域名只比较主机名，http 通常会被重定向到 https，换个端口也多半是同一个服务
+    if let Some(base) = config.base_url.as_deref().and_then(|b| b.parse::<Uri>().ok()) {
+        if base.host().map(normalize_host).as_deref() == Some(host) {
+            return true;
+        }
+    }
+    let Ok(listen) = config.listen_addr.parse::<SocketAddr>() else {
+        return false;
+    };
+    if port != Some(listen.port()) {
+        return false;
+    }
+    let ip = match host {
+        "localhost" => Ok(IpAddr::from([127, 0, 0, 1])),
+        _ => host.parse::<IpAddr>(),
+    };
+    let Ok(ip) = ip else {
+        return false;
+    };
+    if ip == listen.ip() {
+        return true;
+    }
+    // 监听 0.0.0.0 / :: 时通过回环地址也能访问到自己
+    (listen.ip().is_unspecified() || listen.ip().is_loopback()) && (ip.is_loopback() || ip.is_unspecified())
+}
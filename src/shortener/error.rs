@@ -0,0 +1,178 @@
+use std::time::Duration;
+use axum::extract::rejection::{BytesRejection, JsonRejection, QueryRejection};
+use axum::extract::Request;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use http::header::RETRY_AFTER;
+use http::{HeaderName, HeaderValue, StatusCode};
+use serde::Serialize;
+use thiserror::Error;
+use tracing::{info, warn};
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+tokio::task_local! {
+    // 由 request_id 中间件设置，错误响应里会带上
+    static REQUEST_ID: String;
+}
+
+/// Error returned by the shortener handlers. It is rendered as
+/// `{"code": ..., "message": ..., "request_id": ...}`, where `code` is one of
+/// the stable strings from [`AppError::code`].
+#[derive(Debug, Error)]
+pub enum AppError {
+    #[error("{0}")]
+    InvalidInput(String),
+    // 请求体、查询参数等无法解析
+    #[error("{1}")]
+    InvalidRequest(StatusCode, String),
+    #[error("missing or unknown API key")]
+    Unauthorized,
+    #[error("{0}")]
+    Forbidden(String),
+    #[error("link not found")]
+    NotFound,
+    #[error("link has expired or used up its visits")]
+    Gone,
+    #[error("id is already taken")]
+    Conflict,
+    // 附带多久之后可以重试
+    #[error("too many requests, retry in {}s", retry_secs(.0))]
+    RateLimited(Duration),
+    #[error("database unavailable: {0}")]
+    Unavailable(anyhow::Error),
+    #[error("internal error: {0}")]
+    Internal(anyhow::Error),
+}
+
+#[derive(Debug, Serialize)]
+struct ErrorBody {
+    code: &'static str,
+    message: String,
+    request_id: Option<String>,
+}
+
+impl AppError {
+    /// Classify a storage error: lost connections and pool timeouts mean the
+    /// database is unavailable, anything else is a bug.
+    pub fn storage(err: anyhow::Error) -> Self {
+        match err.downcast_ref::<sqlx::Error>() {
+            Some(
+                sqlx::Error::Io(_)
+                | sqlx::Error::Tls(_)
+                | sqlx::Error::PoolTimedOut
+                | sqlx::Error::PoolClosed
+                | sqlx::Error::WorkerCrashed,
+            ) => Self::Unavailable(err),
+            _ => Self::Internal(err),
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::InvalidInput(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::InvalidRequest(status, _) => *status,
+            Self::Unauthorized => StatusCode::UNAUTHORIZED,
+            Self::Forbidden(_) => StatusCode::FORBIDDEN,
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::Gone => StatusCode::GONE,
+            Self::Conflict => StatusCode::CONFLICT,
+            Self::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            Self::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::InvalidInput(_) => "invalid_input",
+            Self::InvalidRequest(..) => "invalid_request",
+            Self::Unauthorized => "unauthorized",
+            Self::Forbidden(_) => "forbidden",
+            Self::NotFound => "not_found",
+            Self::Gone => "gone",
+            Self::Conflict => "conflict",
+            Self::RateLimited(_) => "rate_limited",
+            Self::Unavailable(_) => "unavailable",
+            Self::Internal(_) => "internal",
+        }
+    }
+
+    /// Message safe to show to clients: the cause of server errors stays in
+    /// the log.
+    pub fn message(&self) -> String {
+        match self {
+            Self::Unavailable(_) => "database unavailable".to_string(),
+            Self::Internal(_) => "internal server error".to_string(),
+            _ => self.to_string(),
+        }
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let request_id = REQUEST_ID.try_with(|id| id.clone()).ok();
+        let status = self.status();
+        let retry_after = match &self {
+            Self::RateLimited(wait) => Some(retry_secs(wait)),
+            _ => None,
+        };
+        let id = request_id.as_deref().unwrap_or("-");
+        if status.is_server_error() {
+            warn!("[{}] {}", id, self);
+        } else {
+            info!("[{}] {}: {}", id, self.code(), self);
+        }
+        let body = ErrorBody {
+            code: self.code(),
+            message: self.message(),
+            request_id,
+        };
+        let mut res = (status, Json(body)).into_response();
+        if let Some(secs) = retry_after {
+            res.headers_mut().insert(RETRY_AFTER, HeaderValue::from(secs));
+        }
+        res
+    }
+}
+
+// Retry-After 只能是整数秒，向上取整避免客户端过早重试
+fn retry_secs(wait: &Duration) -> u64 {
+    wait.as_secs_f64().ceil().max(1.0) as u64
+}
+
+impl From<JsonRejection> for AppError {
+    fn from(rejection: JsonRejection) -> Self {
+        Self::InvalidRequest(rejection.status(), rejection.body_text())
+    }
+}
+
+impl From<QueryRejection> for AppError {
+    fn from(rejection: QueryRejection) -> Self {
+        Self::InvalidRequest(rejection.status(), rejection.body_text())
+    }
+}
+
+impl From<BytesRejection> for AppError {
+    fn from(rejection: BytesRejection) -> Self {
+        Self::InvalidRequest(rejection.status(), rejection.body_text())
+    }
+}
+
+/// Give every request an id, taken from `x-request-id` when the client sent
+/// a usable one, and echo it in the response.
+pub async fn request_id(req: Request, next: Next) -> Response {
+    let id = req
+        .headers()
+        .get(&REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|id| !id.is_empty() && id.len() <= 64)
+        .map(str::to_string)
+        .unwrap_or_else(|| nanoid::nanoid!());
+    let mut res = REQUEST_ID.scope(id.clone(), next.run(req)).await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        res.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    res
+}
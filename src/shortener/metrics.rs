@@ -0,0 +1,49 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Counters exposed on `GET /metrics` in the Prometheus text format.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    pub links_shortened: AtomicU64,
+    // 生成的 id 已经被其他 url 占用
+    pub id_collisions: AtomicU64,
+    // 重试次数用完仍然没有拿到可用的 id
+    pub id_exhausted: AtomicU64,
+    pub clicks_recorded: AtomicU64,
+    // 队列已满或者写入失败而丢弃的点击记录
+    pub clicks_dropped: AtomicU64,
+    pub cache_hits: AtomicU64,
+    pub cache_misses: AtomicU64,
+    // 因为超出频率限制被拒绝的请求
+    pub rate_limited: AtomicU64,
+}
+
+impl Metrics {
+    pub fn inc(counter: &AtomicU64) {
+        Self::add(counter, 1);
+    }
+
+    pub fn add(counter: &AtomicU64, n: u64) {
+        counter.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn render(&self) -> String {
+        let counters = [
+            ("shortener_links_shortened_total", "Successful shorten requests.", &self.links_shortened),
+            ("shortener_id_collisions_total", "Generated ids that were already taken.", &self.id_collisions),
+            ("shortener_id_exhausted_total", "Shorten requests that ran out of id attempts.", &self.id_exhausted),
+            ("shortener_clicks_recorded_total", "Clicks written to storage.", &self.clicks_recorded),
+            ("shortener_clicks_dropped_total", "Clicks dropped because the queue was full or the write failed.", &self.clicks_dropped),
+            ("shortener_cache_hits_total", "Link lookups answered from the cache.", &self.cache_hits),
+            ("shortener_cache_misses_total", "Link lookups that went to the database.", &self.cache_misses),
+            ("shortener_rate_limited_total", "Requests rejected by the rate limiter.", &self.rate_limited),
+        ];
+        let mut out = String::new();
+        for (name, help, value) in counters {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} counter", name);
+            let _ = writeln!(out, "{} {}", name, value.load(Ordering::Relaxed));
+        }
+        out
+    }
+}
@@ -0,0 +1,132 @@
+use axum::extract::rejection::QueryRejection;
+use axum::extract::{Path, Query, State};
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::Router;
+use http::header::{CACHE_CONTROL, CONTENT_TYPE};
+use qrcode::render::svg;
+use qrcode::{Color, EcLevel, QrCode};
+use serde::Deserialize;
+use super::{AppError, AppState};
+
+const MIN_SIZE: u32 = 64;
+const MAX_SIZE: u32 = 2048;
+// 标准要求四周至少留 4 个模块宽的空白
+const QUIET_ZONE: usize = 4;
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum QrFormat {
+    #[default]
+    Png,
+    Svg,
+}
+
+// 纠错等级越高，能容忍的污损越多，图案也越密
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Ec {
+    L,
+    #[default]
+    M,
+    Q,
+    H,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+struct QrQuery {
+    format: QrFormat,
+    // 图片的最小边长，单位是像素
+    size: u32,
+    ec: Ec,
+}
+
+impl Default for QrQuery {
+    fn default() -> Self {
+        Self {
+            format: QrFormat::default(),
+            size: 256,
+            ec: Ec::default(),
+        }
+    }
+}
+
+impl From<Ec> for EcLevel {
+    fn from(ec: Ec) -> Self {
+        match ec {
+            Ec::L => EcLevel::L,
+            Ec::M => EcLevel::M,
+            Ec::Q => EcLevel::Q,
+            Ec::H => EcLevel::H,
+        }
+    }
+}
+
+pub(super) fn routes() -> Router<AppState> {
+    Router::new().route("/:id/qr", get(qr_code))
+}
+
+async fn qr_code(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    query: Result<Query<QrQuery>, QueryRejection>,
+) -> Result<impl IntoResponse, AppError> {
+
+    let Query(query) = query?;
+    if !(MIN_SIZE..=MAX_SIZE).contains(&query.size) {
+        return Err(AppError::InvalidInput(format!("size must be between {} and {}", MIN_SIZE, MAX_SIZE)));
+    }
+    if state.store.get(&id).await.map_err(AppError::storage)?.is_none() {
+        return Err(AppError::NotFound);
+    }
+
+    let short_url = format!("{}/{}", state.config.public_base_url(), id);
+    let code = QrCode::with_error_correction_level(&short_url, query.ec.into())
+        .map_err(|e| AppError::InvalidInput(format!("cannot encode {} as a QR code: {}", short_url, e)))?;
+    let (content_type, body) = match query.format {
+        QrFormat::Svg => {
+            let image = code
+                .render::<svg::Color>()
+                .min_dimensions(query.size, query.size)
+                .build();
+            ("image/svg+xml", image.into_bytes())
+        }
+        QrFormat::Png => {
+            let image = render_png(&code, query.size).map_err(|e| AppError::Internal(e.into()))?;
+            ("image/png", image)
+        }
+    };
+    // 同一个 id 的二维码不会变
+    Ok(([(CONTENT_TYPE, content_type), (CACHE_CONTROL, "public, max-age=86400")], body))
+}
+
+/// Render `code` as an 8-bit grayscale PNG at least `size` pixels wide,
+/// using a whole number of pixels per module so it stays sharp.
+fn render_png(code: &QrCode, size: u32) -> Result<Vec<u8>, png::EncodingError> {
+    let modules = code.width() + 2 * QUIET_ZONE;
+    let scale = (size as usize).div_ceil(modules);
+    let dim = modules * scale;
+    let colors = code.to_colors();
+
+    let mut pixels = vec![0xffu8; dim * dim];
+    for (idx, color) in colors.iter().enumerate() {
+        if *color != Color::Dark {
+            continue;
+        }
+        let x = (idx % code.width() + QUIET_ZONE) * scale;
+        let y = (idx / code.width() + QUIET_ZONE) * scale;
+        for row in y..y + scale {
+            pixels[row * dim + x..row * dim + x + scale].fill(0);
+        }
+    }
+
+    let mut out = Vec::new();
+    let mut encoder = png::Encoder::new(&mut out, dim as u32, dim as u32);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&pixels)?;
+    writer.finish()?;
+    Ok(out)
+}
@@ -0,0 +1,154 @@
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use axum::extract::{ConnectInfo, Request};
+use axum::response::{IntoResponse, Response};
+use dashmap::DashMap;
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use tower::{Layer, Service};
+use super::{AppError, AppState, Metrics};
+
+// 每处理这么多次请求清理一次已经回满的桶
+const SWEEP_EVERY: u64 = 1024;
+
+/// Token bucket settings: up to `burst` requests at once, refilled at
+/// `per_minute` requests per minute.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct RateLimit {
+    pub burst: u32,
+    pub per_minute: u32,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    // 什么时候回满，之后这个桶和新建的没有区别
+    full_at: Instant,
+}
+
+/// Token buckets keyed by client, `ip:<addr>` or `key:<owner>`.
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    buckets: DashMap<String, Bucket>,
+    checks: AtomicU64,
+}
+
+impl RateLimit {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.burst == 0 || self.per_minute == 0 {
+            anyhow::bail!("rate limit burst and per_minute must be at least 1");
+        }
+        Ok(())
+    }
+
+    fn per_sec(&self) -> f64 {
+        self.per_minute as f64 / 60.0
+    }
+}
+
+impl RateLimiter {
+    /// Take a token from the bucket of `key`. When it is empty, returns how
+    /// long until the next token.
+    pub fn check(&self, key: &str, limit: &RateLimit, now: Instant) -> Result<(), Duration> {
+        if self.checks.fetch_add(1, Ordering::Relaxed).is_multiple_of(SWEEP_EVERY) {
+            self.buckets.retain(|_, bucket| bucket.full_at > now);
+        }
+        let mut bucket = self.buckets.entry(key.to_string()).or_insert_with(|| Bucket {
+            tokens: limit.burst as f64,
+            updated: now,
+            full_at: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * limit.per_sec()).min(limit.burst as f64);
+        bucket.updated = now;
+        if bucket.tokens < 1.0 {
+            return Err(Duration::from_secs_f64((1.0 - bucket.tokens) / limit.per_sec()));
+        }
+        bucket.tokens -= 1.0;
+        let missing = limit.burst as f64 - bucket.tokens;
+        bucket.full_at = now + Duration::from_secs_f64(missing / limit.per_sec());
+        Ok(())
+    }
+}
+
+/// Limits requests per API key owner, or per client IP for requests without
+/// a valid key, using the limits from the shortener config.
+#[derive(Clone)]
+pub struct RateLimitLayer {
+    state: AppState,
+}
+
+#[derive(Clone)]
+pub struct RateLimitService<S> {
+    inner: S,
+    state: AppState,
+}
+
+impl RateLimitLayer {
+    pub fn new(state: AppState) -> Self {
+        Self { state }
+    }
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimitService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimitService {
+            inner,
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<S> RateLimitService<S> {
+    fn check(&self, req: &Request) -> Result<(), AppError> {
+        let config = &self.state.config;
+        // key 不对时按 IP 限流，交给后面的 handler 返回 401
+        let (key, limit) = match self.state.authenticate(req.headers()) {
+            Ok(Some(owner)) => (format!("key:{}", owner), config.api_key_rate_limit),
+            _ => {
+                let ip = req
+                    .extensions()
+                    .get::<ConnectInfo<SocketAddr>>()
+                    .map(|ConnectInfo(addr)| addr.ip().to_string())
+                    .unwrap_or_default();
+                (format!("ip:{}", ip), config.ip_rate_limit)
+            }
+        };
+        let Some(limit) = limit else {
+            return Ok(());
+        };
+        self.state
+            .rate_limiter
+            .check(&key, &limit, Instant::now())
+            .map_err(|retry_after| {
+                Metrics::inc(&self.state.metrics.rate_limited);
+                AppError::RateLimited(retry_after)
+            })
+    }
+}
+
+impl<S> Service<Request> for RateLimitService<S>
+where
+    S: Service<Request, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Response, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        match self.check(&req) {
+            Ok(()) => Box::pin(self.inner.call(req)),
+            Err(err) => Box::pin(async move { Ok(err.into_response()) }),
+        }
+    }
+}
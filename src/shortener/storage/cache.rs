@@ -0,0 +1,157 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use hashlink::LruCache;
+use super::{Click, Link, LinkStats, SchemaVersion, Storage};
+use crate::shortener::Metrics;
+
+struct Entry {
+    // None 表示数据库里没有这个 id
+    link: Option<Link>,
+    expires: Instant,
+}
+
+/// Read-through LRU cache for `get` in front of another storage. Misses are
+/// cached too, for a shorter time. Entries are dropped whenever this process
+/// changes the link; changes made by other processes show up once the entry
+/// expires.
+pub struct CachedStorage {
+    inner: Arc<dyn Storage>,
+    entries: Mutex<LruCache<String, Entry>>,
+    ttl: Duration,
+    negative_ttl: Duration,
+    // 每次失效都加一，用来丢弃失效之前就开始的查询结果
+    generation: AtomicU64,
+    metrics: Arc<Metrics>,
+}
+
+impl CachedStorage {
+    pub fn new(
+        inner: Arc<dyn Storage>,
+        capacity: usize,
+        ttl: Duration,
+        negative_ttl: Duration,
+        metrics: Arc<Metrics>,
+    ) -> Self {
+        Self {
+            inner,
+            entries: Mutex::new(LruCache::new(capacity)),
+            ttl,
+            negative_ttl,
+            generation: AtomicU64::new(0),
+            metrics,
+        }
+    }
+
+    fn lookup(&self, id: &str) -> Option<Option<Link>> {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.get(id)?;
+        if entry.expires <= Instant::now() {
+            entries.remove(id);
+            return None;
+        }
+        Some(entry.link.clone())
+    }
+
+    fn invalidate(&self, id: &str) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        self.entries.lock().unwrap().remove(id);
+    }
+
+    fn invalidate_all(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+#[async_trait]
+impl Storage for CachedStorage {
+    async fn shorten(&self, id: &str, url: &str) -> Result<Option<String>> {
+        let ret = self.inner.shorten(id, url).await?;
+        if let Some(id) = &ret {
+            self.invalidate(id);
+        }
+        Ok(ret)
+    }
+
+    async fn create(&self, link: &Link) -> Result<bool> {
+        let created = self.inner.create(link).await?;
+        if created {
+            self.invalidate(&link.id);
+        }
+        Ok(created)
+    }
+
+    async fn get(&self, id: &str) -> Result<Option<Link>> {
+        if let Some(link) = self.lookup(id) {
+            Metrics::inc(&self.metrics.cache_hits);
+            return Ok(link);
+        }
+        Metrics::inc(&self.metrics.cache_misses);
+
+        let generation = self.generation.load(Ordering::SeqCst);
+        let link = self.inner.get(id).await?;
+        let ttl = if link.is_some() { self.ttl } else { self.negative_ttl };
+        let mut entries = self.entries.lock().unwrap();
+        if !ttl.is_zero() && generation == self.generation.load(Ordering::SeqCst) {
+            let entry = Entry {
+                link: link.clone(),
+                expires: Instant::now() + ttl,
+            };
+            entries.insert(id.to_string(), entry);
+        }
+        Ok(link)
+    }
+
+    async fn record_visit(&self, id: &str) -> Result<bool> {
+        let counted = self.inner.record_visit(id).await?;
+        // 缓存里的 visits 已经过时
+        self.invalidate(id);
+        Ok(counted)
+    }
+
+    async fn purge_expired(&self, now: DateTime<Utc>) -> Result<u64> {
+        let n = self.inner.purge_expired(now).await?;
+        if n > 0 {
+            self.invalidate_all();
+        }
+        Ok(n)
+    }
+
+    async fn list(&self, owner: Option<&str>, after: Option<&str>, limit: usize) -> Result<Vec<Link>> {
+        self.inner.list(owner, after, limit).await
+    }
+
+    async fn update_url(&self, id: &str, owner: &str, url: &str) -> Result<bool> {
+        let updated = self.inner.update_url(id, owner, url).await?;
+        self.invalidate(id);
+        Ok(updated)
+    }
+
+    async fn delete(&self, id: &str, owner: &str) -> Result<bool> {
+        let deleted = self.inner.delete(id, owner).await?;
+        self.invalidate(id);
+        Ok(deleted)
+    }
+
+    async fn record_clicks(&self, clicks: &[Click]) -> Result<()> {
+        self.inner.record_clicks(clicks).await
+    }
+
+    async fn stats(&self, id: &str, since: DateTime<Utc>, top: usize) -> Result<LinkStats> {
+        self.inner.stats(id, since, top).await
+    }
+
+    async fn migrate(&self) -> Result<SchemaVersion> {
+        let version = self.inner.migrate().await?;
+        self.invalidate_all();
+        Ok(version)
+    }
+
+    async fn schema_version(&self) -> Result<SchemaVersion> {
+        self.inner.schema_version().await
+    }
+}
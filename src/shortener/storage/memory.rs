@@ -0,0 +1,151 @@
+use std::collections::{BTreeMap, HashMap};
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use super::{Click, DailyClicks, Link, LinkStats, ReferrerClicks, Storage};
+
+/// Keeps everything in process memory, for tests and local development.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    links: DashMap<String, Link>,
+    // url -> id，用来实现重复 url 返回同一个 id，不包含 create 创建的链接
+    ids: DashMap<String, String>,
+    clicks: DashMap<String, Vec<Click>>,
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn shorten(&self, id: &str, url: &str) -> Result<Option<String>> {
+        match self.ids.entry(url.to_string()) {
+            Entry::Occupied(entry) => Ok(Some(entry.get().clone())),
+            Entry::Vacant(entry) => match self.links.entry(id.to_string()) {
+                Entry::Occupied(_) => Ok(None),
+                Entry::Vacant(slot) => {
+                    slot.insert(Link::new(id, url));
+                    entry.insert(id.to_string());
+                    Ok(Some(id.to_string()))
+                }
+            },
+        }
+    }
+
+    async fn create(&self, link: &Link) -> Result<bool> {
+        match self.links.entry(link.id.clone()) {
+            Entry::Occupied(_) => Ok(false),
+            Entry::Vacant(slot) => {
+                slot.insert(link.clone());
+                Ok(true)
+            }
+        }
+    }
+
+    async fn get(&self, id: &str) -> Result<Option<Link>> {
+        Ok(self.links.get(id).map(|link| link.clone()))
+    }
+
+    async fn record_visit(&self, id: &str) -> Result<bool> {
+        let Some(mut link) = self.links.get_mut(id) else {
+            return Ok(false);
+        };
+        if link.is_exhausted() {
+            return Ok(false);
+        }
+        link.visits += 1;
+        Ok(true)
+    }
+
+    async fn purge_expired(&self, now: DateTime<Utc>) -> Result<u64> {
+        let mut purged = Vec::new();
+        self.links.retain(|id, link| {
+            let keep = !(link.is_expired(now) || link.is_exhausted());
+            if !keep {
+                purged.push(id.clone());
+            }
+            keep
+        });
+        if purged.is_empty() {
+            return Ok(0);
+        }
+        self.ids.retain(|_, id| self.links.contains_key(id));
+        for id in &purged {
+            self.clicks.remove(id);
+        }
+        Ok(purged.len() as u64)
+    }
+
+    async fn list(&self, owner: Option<&str>, after: Option<&str>, limit: usize) -> Result<Vec<Link>> {
+        let mut links: Vec<Link> = self
+            .links
+            .iter()
+            .filter(|link| owner.is_none_or(|owner| link.owner.as_deref() == Some(owner)))
+            .filter(|link| after.is_none_or(|after| link.id.as_str() > after))
+            .map(|link| link.clone())
+            .collect();
+        links.sort_by(|a, b| a.id.cmp(&b.id));
+        links.truncate(limit);
+        Ok(links)
+    }
+
+    async fn update_url(&self, id: &str, owner: &str, url: &str) -> Result<bool> {
+        match self.links.get_mut(id) {
+            Some(mut link) if link.owner.as_deref() == Some(owner) => {
+                link.url = url.to_string();
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn delete(&self, id: &str, owner: &str) -> Result<bool> {
+        let deleted = self
+            .links
+            .remove_if(id, |_, link| link.owner.as_deref() == Some(owner))
+            .is_some();
+        if deleted {
+            self.clicks.remove(id);
+        }
+        Ok(deleted)
+    }
+
+    async fn record_clicks(&self, clicks: &[Click]) -> Result<()> {
+        for click in clicks {
+            self.clicks
+                .entry(click.link_id.clone())
+                .or_default()
+                .push(click.clone());
+        }
+        Ok(())
+    }
+
+    async fn stats(&self, id: &str, since: DateTime<Utc>, top: usize) -> Result<LinkStats> {
+        let Some(clicks) = self.clicks.get(id) else {
+            return Ok(LinkStats::default());
+        };
+        let mut days = BTreeMap::new();
+        let mut referrers = HashMap::new();
+        for click in clicks.iter() {
+            if click.clicked_at >= since {
+                *days.entry(click.clicked_at.date_naive()).or_insert(0) += 1;
+            }
+            if let Some(referrer) = &click.referrer {
+                *referrers.entry(referrer.as_str()).or_insert(0) += 1;
+            }
+        }
+        let mut top_referrers: Vec<_> = referrers
+            .into_iter()
+            .map(|(referrer, clicks)| ReferrerClicks { referrer: referrer.to_string(), clicks })
+            .collect();
+        top_referrers.sort_by(|a, b| b.clicks.cmp(&a.clicks).then_with(|| a.referrer.cmp(&b.referrer)));
+        top_referrers.truncate(top);
+        Ok(LinkStats {
+            total_clicks: clicks.len() as i64,
+            clicks_per_day: days
+                .into_iter()
+                .map(|(day, clicks)| DailyClicks { day, clicks })
+                .collect(),
+            top_referrers,
+        })
+    }
+}
@@ -0,0 +1,62 @@
+use anyhow::{bail, Result};
+use sqlx::migrate::{Migrate, Migrator};
+
+/// Schema version of a database next to the newest one this build knows.
+/// Versions are the numeric prefixes of the files in `migrations/`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SchemaVersion {
+    // 0 表示还没有执行过任何迁移
+    pub applied: i64,
+    pub latest: i64,
+}
+
+impl SchemaVersion {
+    /// Fail unless the database is exactly at the latest version.
+    pub fn check(&self) -> Result<()> {
+        self.check_not_newer()?;
+        if self.applied < self.latest {
+            bail!(
+                "database schema version {} is behind {}, run `shortener migrate` first",
+                self.applied,
+                self.latest
+            );
+        }
+        Ok(())
+    }
+
+    fn check_not_newer(&self) -> Result<()> {
+        if self.applied > self.latest {
+            bail!(
+                "database schema version {} is newer than {} supported by this build, refusing to start",
+                self.applied,
+                self.latest
+            );
+        }
+        Ok(())
+    }
+}
+
+pub(super) async fn schema_version<C: Migrate>(migrator: &Migrator, conn: &mut C) -> Result<SchemaVersion> {
+    conn.ensure_migrations_table().await?;
+    let applied = conn
+        .list_applied_migrations()
+        .await?
+        .iter()
+        .map(|m| m.version)
+        .max()
+        .unwrap_or(0);
+    let latest = migrator.iter().map(|m| m.version).max().unwrap_or(0);
+    Ok(SchemaVersion { applied, latest })
+}
+
+/// Apply pending migrations. A database written by a newer build is left
+/// alone, since this build would not understand its schema.
+pub(super) async fn run<C: Migrate>(migrator: &Migrator, conn: &mut C) -> Result<SchemaVersion> {
+    let version = schema_version(migrator, conn).await?;
+    version.check_not_newer()?;
+    migrator.run_direct(conn).await?;
+    Ok(SchemaVersion {
+        applied: version.latest,
+        ..version
+    })
+}
@@ -0,0 +1,190 @@
+mod cache;
+mod memory;
+mod migrate;
+mod postgres;
+mod sqlite;
+
+use std::sync::Arc;
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+pub use cache::CachedStorage;
+pub use memory::MemoryStorage;
+pub use migrate::SchemaVersion;
+pub use postgres::PgStorage;
+pub use sqlite::SqliteStorage;
+
+#[derive(Debug, Clone, PartialEq, FromRow)]
+pub struct Link {
+    pub id: String,
+    pub url: String,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub max_visits: Option<i64>,
+    pub visits: i64,
+    // 为空时使用全局配置的状态码
+    pub redirect_status: Option<RedirectStatus>,
+    // 创建链接时使用的 API key 对应的用户，只有他能管理这个链接
+    pub owner: Option<String>,
+}
+
+/// Status code used when redirecting to a link, stored as the numeric code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(try_from = "u16", into = "u16")]
+#[repr(i32)]
+pub enum RedirectStatus {
+    MovedPermanently = 301,
+    Found = 302,
+    TemporaryRedirect = 307,
+    PermanentRedirect = 308,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Click {
+    pub link_id: String,
+    pub clicked_at: DateTime<Utc>,
+    pub referrer: Option<String>,
+    pub user_agent: Option<String>,
+    // 加盐哈希后的客户端 IP
+    pub ip_hash: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct LinkStats {
+    pub total_clicks: i64,
+    pub clicks_per_day: Vec<DailyClicks>,
+    pub top_referrers: Vec<ReferrerClicks>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, FromRow)]
+pub struct DailyClicks {
+    pub day: NaiveDate,
+    pub clicks: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, FromRow)]
+pub struct ReferrerClicks {
+    pub referrer: String,
+    pub clicks: i64,
+}
+
+#[async_trait]
+pub trait Storage: Send + Sync + 'static {
+    /// Store `url` under `id`. If the url was shortened before, the id it
+    /// already has is returned instead. Returns `None` when `id` is already
+    /// taken by a different url.
+    async fn shorten(&self, id: &str, url: &str) -> Result<Option<String>>;
+
+    /// Store `link` as a new entry that is never shared with other requests
+    /// for the same url, as needed for aliases and links with limits.
+    /// Returns `false` if the id is taken.
+    async fn create(&self, link: &Link) -> Result<bool>;
+
+    async fn get(&self, id: &str) -> Result<Option<Link>>;
+
+    /// Count a visit to a link with `max_visits`. Returns `false` without
+    /// counting when the link has no visits left.
+    async fn record_visit(&self, id: &str) -> Result<bool>;
+
+    /// Delete links that expired before `now` or used up their visits,
+    /// returning how many were removed. Their clicks are deleted too.
+    async fn purge_expired(&self, now: DateTime<Utc>) -> Result<u64>;
+
+    /// Links ordered by id, starting after `after`. Only links of `owner`
+    /// unless it is `None`.
+    async fn list(&self, owner: Option<&str>, after: Option<&str>, limit: usize) -> Result<Vec<Link>>;
+
+    /// Point a link owned by `owner` at `url`. Returns `false` if there is no
+    /// such link.
+    async fn update_url(&self, id: &str, owner: &str, url: &str) -> Result<bool>;
+
+    /// Delete a link owned by `owner` together with its clicks.
+    async fn delete(&self, id: &str, owner: &str) -> Result<bool>;
+
+    async fn record_clicks(&self, clicks: &[Click]) -> Result<()>;
+
+    /// Total clicks on `id`, clicks per UTC day since `since` and the `top`
+    /// most common referrers.
+    async fn stats(&self, id: &str, since: DateTime<Utc>, top: usize) -> Result<LinkStats>;
+
+    /// Apply pending schema migrations. Backends without a schema have
+    /// nothing to do.
+    async fn migrate(&self) -> Result<SchemaVersion> {
+        Ok(SchemaVersion::default())
+    }
+
+    async fn schema_version(&self) -> Result<SchemaVersion> {
+        Ok(SchemaVersion::default())
+    }
+}
+
+impl Link {
+    pub fn new(id: impl Into<String>, url: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            url: url.into(),
+            expires_at: None,
+            max_visits: None,
+            visits: 0,
+            redirect_status: None,
+            owner: None,
+        }
+    }
+
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.max_visits.is_some_and(|max| self.visits >= max)
+    }
+}
+
+impl RedirectStatus {
+    pub fn status_code(self) -> StatusCode {
+        match self {
+            Self::MovedPermanently => StatusCode::MOVED_PERMANENTLY,
+            Self::Found => StatusCode::FOUND,
+            Self::TemporaryRedirect => StatusCode::TEMPORARY_REDIRECT,
+            Self::PermanentRedirect => StatusCode::PERMANENT_REDIRECT,
+        }
+    }
+}
+
+impl TryFrom<u16> for RedirectStatus {
+    type Error = String;
+
+    fn try_from(code: u16) -> Result<Self, Self::Error> {
+        match code {
+            301 => Ok(Self::MovedPermanently),
+            302 => Ok(Self::Found),
+            307 => Ok(Self::TemporaryRedirect),
+            308 => Ok(Self::PermanentRedirect),
+            _ => Err(format!("redirect status must be 301, 302, 307 or 308, got {}", code)),
+        }
+    }
+}
+
+impl From<RedirectStatus> for u16 {
+    fn from(status: RedirectStatus) -> Self {
+        status as u16
+    }
+}
+
+/// Open the storage backend matching the scheme of `url`:
+/// `postgres://...`, `sqlite:...` or `memory://`.
+pub async fn connect(url: &str) -> Result<Arc<dyn Storage>> {
+    let storage: Arc<dyn Storage> = if url.starts_with("postgres://") || url.starts_with("postgresql://") {
+        Arc::new(PgStorage::try_new(url).await?)
+    } else if url.starts_with("sqlite:") {
+        Arc::new(SqliteStorage::try_new(url).await?)
+    } else if url.starts_with("memory:") {
+        Arc::new(MemoryStorage::default())
+    } else {
+        bail!("unsupported database url: {}", url);
+    };
+    Ok(storage)
+}
@@ -0,0 +1,185 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::migrate::Migrator;
+use sqlx::{PgPool, QueryBuilder};
+use super::{migrate, Click, DailyClicks, Link, LinkStats, ReferrerClicks, SchemaVersion, Storage};
+
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations/postgres");
+
+#[derive(Debug, Clone)]
+pub struct PgStorage {
+    db: PgPool,
+}
+
+impl PgStorage {
+    pub async fn try_new(url: &str) -> Result<Self> {
+        let pool = PgPool::connect(url).await?;
+        Ok(Self { db: pool })
+    }
+}
+
+#[async_trait]
+impl Storage for PgStorage {
+    async fn shorten(&self, id: &str, url: &str) -> Result<Option<String>> {
+        let ret = sqlx::query_scalar::<_, String>(
+            "INSERT INTO urls (id, url) VALUES ($1, $2) ON CONFLICT(url) WHERE NOT custom DO UPDATE SET url=EXCLUDED.url RETURNING id",
+        ).bind(id)
+            .bind(url)
+            .fetch_one(&self.db)
+            .await;
+
+        match ret {
+            Ok(id) => Ok(Some(id)),
+            // url 冲突已经由 ON CONFLICT 处理，剩下的唯一约束冲突只可能是 id
+            Err(sqlx::Error::Database(e)) if e.is_unique_violation() => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn create(&self, link: &Link) -> Result<bool> {
+        let ret = sqlx::query(
+            "INSERT INTO urls (id, url, custom, expires_at, max_visits, visits, redirect_status, owner) VALUES ($1, $2, TRUE, $3, $4, $5, $6, $7) ON CONFLICT(id) DO NOTHING",
+        ).bind(&link.id)
+            .bind(&link.url)
+            .bind(link.expires_at)
+            .bind(link.max_visits)
+            .bind(link.visits)
+            .bind(link.redirect_status)
+            .bind(&link.owner)
+            .execute(&self.db)
+            .await?;
+        Ok(ret.rows_affected() == 1)
+    }
+
+    async fn get(&self, id: &str) -> Result<Option<Link>> {
+        let ret = sqlx::query_as::<_, Link>(
+            "SELECT id, url, expires_at, max_visits, visits, redirect_status, owner FROM urls WHERE id = $1",
+        ).bind(id)
+            .fetch_optional(&self.db)
+            .await?;
+        Ok(ret)
+    }
+
+    async fn record_visit(&self, id: &str) -> Result<bool> {
+        let ret = sqlx::query(
+            "UPDATE urls SET visits = visits + 1 WHERE id = $1 AND (max_visits IS NULL OR visits < max_visits)",
+        ).bind(id)
+            .execute(&self.db)
+            .await?;
+        Ok(ret.rows_affected() == 1)
+    }
+
+    async fn purge_expired(&self, now: DateTime<Utc>) -> Result<u64> {
+        let mut tx = self.db.begin().await?;
+        let ids = sqlx::query_scalar::<_, String>(
+            "DELETE FROM urls WHERE expires_at <= $1 OR visits >= max_visits RETURNING id",
+        ).bind(now)
+            .fetch_all(&mut *tx)
+            .await?;
+        if ids.is_empty() {
+            return Ok(0);
+        }
+        // 只删除被清理的链接的点击记录，不扫描整张 clicks 表
+        sqlx::query("DELETE FROM clicks WHERE link_id = ANY($1)")
+            .bind(&ids)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(ids.len() as u64)
+    }
+
+    async fn list(&self, owner: Option<&str>, after: Option<&str>, limit: usize) -> Result<Vec<Link>> {
+        let ret = sqlx::query_as::<_, Link>(
+            "SELECT id, url, expires_at, max_visits, visits, redirect_status, owner FROM urls WHERE ($1 IS NULL OR owner = $1) AND ($2 IS NULL OR id > $2) ORDER BY id LIMIT $3",
+        ).bind(owner)
+            .bind(after)
+            .bind(limit as i64)
+            .fetch_all(&self.db)
+            .await?;
+        Ok(ret)
+    }
+
+    async fn update_url(&self, id: &str, owner: &str, url: &str) -> Result<bool> {
+        let ret = sqlx::query(
+            "UPDATE urls SET url = $1 WHERE id = $2 AND owner = $3",
+        ).bind(url)
+            .bind(id)
+            .bind(owner)
+            .execute(&self.db)
+            .await?;
+        Ok(ret.rows_affected() == 1)
+    }
+
+    async fn delete(&self, id: &str, owner: &str) -> Result<bool> {
+        let mut tx = self.db.begin().await?;
+        let ret = sqlx::query(
+            "DELETE FROM urls WHERE id = $1 AND owner = $2",
+        ).bind(id)
+            .bind(owner)
+            .execute(&mut *tx)
+            .await?;
+        if ret.rows_affected() == 0 {
+            return Ok(false);
+        }
+        sqlx::query("DELETE FROM clicks WHERE link_id = $1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn record_clicks(&self, clicks: &[Click]) -> Result<()> {
+        if clicks.is_empty() {
+            return Ok(());
+        }
+        let mut query = QueryBuilder::new(
+            "INSERT INTO clicks (link_id, clicked_at, referrer, user_agent, ip_hash) ",
+        );
+        query.push_values(clicks, |mut row, click| {
+            row.push_bind(&click.link_id)
+                .push_bind(click.clicked_at)
+                .push_bind(&click.referrer)
+                .push_bind(&click.user_agent)
+                .push_bind(&click.ip_hash);
+        });
+        query.build().execute(&self.db).await?;
+        Ok(())
+    }
+
+    async fn stats(&self, id: &str, since: DateTime<Utc>, top: usize) -> Result<LinkStats> {
+        let total_clicks = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM clicks WHERE link_id = $1",
+        ).bind(id)
+            .fetch_one(&self.db)
+            .await?;
+        let clicks_per_day = sqlx::query_as::<_, DailyClicks>(
+            "SELECT (clicked_at AT TIME ZONE 'UTC')::date AS day, COUNT(*) AS clicks FROM clicks WHERE link_id = $1 AND clicked_at >= $2 GROUP BY day ORDER BY day",
+        ).bind(id)
+            .bind(since)
+            .fetch_all(&self.db)
+            .await?;
+        let top_referrers = sqlx::query_as::<_, ReferrerClicks>(
+            "SELECT referrer, COUNT(*) AS clicks FROM clicks WHERE link_id = $1 AND referrer IS NOT NULL GROUP BY referrer ORDER BY clicks DESC, referrer LIMIT $2",
+        ).bind(id)
+            .bind(top as i64)
+            .fetch_all(&self.db)
+            .await?;
+        Ok(LinkStats {
+            total_clicks,
+            clicks_per_day,
+            top_referrers,
+        })
+    }
+
+    async fn migrate(&self) -> Result<SchemaVersion> {
+        let mut conn = self.db.acquire().await?;
+        migrate::run(&MIGRATOR, &mut *conn).await
+    }
+
+    async fn schema_version(&self) -> Result<SchemaVersion> {
+        let mut conn = self.db.acquire().await?;
+        migrate::schema_version(&MIGRATOR, &mut *conn).await
+    }
+}
@@ -0,0 +1,356 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use _04_ecosystem::minginx::{
+    read_proxy_header, serve_tcp, serve_udp, AccessControl, Listener, ListenerConfig, Protocol,
+    ProxyHeader, ProxyProtocol,
+};
+use anyhow::Result;
+use tokio::io::{self, AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::time::timeout;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+// 把收到的数据原样写回，收到 EOF 后关闭写端
+async fn echo_upstream() -> Result<SocketAddr> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let (mut reader, mut writer) = stream.split();
+                io::copy(&mut reader, &mut writer).await?;
+                writer.shutdown().await
+            });
+        }
+    });
+    Ok(addr)
+}
+
+// 读到 EOF 之后才回复收到的字节数
+async fn sink_upstream() -> Result<SocketAddr> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let n = io::copy(&mut stream, &mut io::sink()).await?;
+                stream.write_all(format!("received {}", n).as_bytes()).await?;
+                stream.shutdown().await
+            });
+        }
+    });
+    Ok(addr)
+}
+
+// 读取 PROXY 头，回复解析出的源地址
+async fn proxy_header_upstream() -> Result<SocketAddr> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let (header, rest) = read_proxy_header(&mut stream).await?;
+                let mut payload = rest.to_vec();
+                stream.read_to_end(&mut payload).await?;
+                let source = header.map(|h| h.source.to_string()).unwrap_or_default();
+                let reply = format!("{} {}", source, String::from_utf8(payload)?);
+                stream.write_all(reply.as_bytes()).await?;
+                stream.shutdown().await?;
+                Ok::<_, anyhow::Error>(())
+            });
+        }
+    });
+    Ok(addr)
+}
+
+async fn start_proxy(config: ListenerConfig) -> Result<SocketAddr> {
+    let tcp_listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = tcp_listener.local_addr()?;
+    tokio::spawn(serve_tcp(Arc::new(Listener::new(config)), tcp_listener));
+    Ok(addr)
+}
+
+fn config_for(upstream: SocketAddr) -> ListenerConfig {
+    ListenerConfig::new("127.0.0.1:0", vec![upstream.to_string()])
+}
+
+fn payload(len: usize) -> Vec<u8> {
+    let mut seed = 0x2545_f491_4f6c_dd1du64;
+    (0..len)
+        .map(|_| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed as u8
+        })
+        .collect()
+}
+
+async fn round_trip(proxy_addr: SocketAddr, data: Vec<u8>) -> Result<Vec<u8>> {
+    let stream = TcpStream::connect(proxy_addr).await?;
+    let (mut reader, mut writer) = stream.into_split();
+    let write = tokio::spawn(async move {
+        writer.write_all(&data).await?;
+        writer.shutdown().await
+    });
+    let mut received = Vec::new();
+    timeout(TIMEOUT, reader.read_to_end(&mut received)).await??;
+    write.await??;
+    Ok(received)
+}
+
+#[tokio::test]
+async fn forwards_bytes_exactly() -> Result<()> {
+    let proxy_addr = start_proxy(config_for(echo_upstream().await?)).await?;
+    let data = payload(4096);
+    assert_eq!(round_trip(proxy_addr, data.clone()).await?, data);
+    Ok(())
+}
+
+#[tokio::test]
+async fn propagates_half_close_to_upstream() -> Result<()> {
+    let proxy_addr = start_proxy(config_for(sink_upstream().await?)).await?;
+    let mut stream = TcpStream::connect(proxy_addr).await?;
+    stream.write_all(b"hello minginx").await?;
+    stream.shutdown().await?;
+
+    // 上游只有在收到 EOF 之后才会回复
+    let mut reply = String::new();
+    timeout(TIMEOUT, stream.read_to_string(&mut reply)).await??;
+    assert_eq!(reply, "received 13");
+    Ok(())
+}
+
+#[tokio::test]
+async fn closes_client_when_upstream_unreachable() -> Result<()> {
+    let unused = TcpListener::bind("127.0.0.1:0").await?;
+    let upstream = unused.local_addr()?;
+    drop(unused);
+
+    let proxy_addr = start_proxy(config_for(upstream)).await?;
+    let mut stream = TcpStream::connect(proxy_addr).await?;
+    let mut buf = Vec::new();
+    let n = timeout(TIMEOUT, stream.read_to_end(&mut buf)).await?.unwrap_or(0);
+    assert_eq!(n, 0);
+    Ok(())
+}
+
+#[tokio::test]
+async fn forwards_large_payload() -> Result<()> {
+    let proxy_addr = start_proxy(config_for(echo_upstream().await?)).await?;
+    let data = payload(32 * 1024 * 1024);
+    let received = round_trip(proxy_addr, data.clone()).await?;
+    assert_eq!(received.len(), data.len());
+    assert!(received == data);
+    Ok(())
+}
+
+#[tokio::test]
+async fn forwards_large_payload_zero_copy() -> Result<()> {
+    let mut config = config_for(echo_upstream().await?);
+    config.zero_copy = true;
+    let proxy_addr = start_proxy(config).await?;
+    let data = payload(32 * 1024 * 1024);
+    let received = round_trip(proxy_addr, data.clone()).await?;
+    assert_eq!(received.len(), data.len());
+    assert!(received == data);
+    Ok(())
+}
+
+#[tokio::test]
+async fn sends_proxy_header_to_upstream() -> Result<()> {
+    let upstream = proxy_header_upstream().await?;
+    for version in [ProxyProtocol::V1, ProxyProtocol::V2] {
+        let mut config = config_for(upstream);
+        config.send_proxy_protocol = Some(version);
+        let proxy_addr = start_proxy(config).await?;
+
+        let mut stream = TcpStream::connect(proxy_addr).await?;
+        let client_addr = stream.local_addr()?;
+        stream.write_all(b"ping").await?;
+        stream.shutdown().await?;
+        let mut reply = String::new();
+        timeout(TIMEOUT, stream.read_to_string(&mut reply)).await??;
+        assert_eq!(reply, format!("{} ping", client_addr));
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn accepts_proxy_header_from_client() -> Result<()> {
+    let mut config = config_for(proxy_header_upstream().await?);
+    config.accept_proxy_protocol = true;
+    config.send_proxy_protocol = Some(ProxyProtocol::V1);
+    let proxy_addr = start_proxy(config).await?;
+
+    let real_client: SocketAddr = "203.0.113.7:40000".parse()?;
+    let header = ProxyHeader {
+        source: real_client,
+        destination: proxy_addr,
+    };
+    let mut stream = TcpStream::connect(proxy_addr).await?;
+    // 头和数据放在同一次写入里，确保头后面多读到的数据也会转发给上游
+    let mut data = header.encode(ProxyProtocol::V2).to_vec();
+    data.extend_from_slice(b"ping");
+    stream.write_all(&data).await?;
+    stream.shutdown().await?;
+
+    let mut reply = String::new();
+    timeout(TIMEOUT, stream.read_to_string(&mut reply)).await??;
+    assert_eq!(reply, format!("{} ping", real_client));
+    Ok(())
+}
+
+#[tokio::test]
+async fn closes_client_without_proxy_header() -> Result<()> {
+    let mut config = config_for(echo_upstream().await?);
+    config.accept_proxy_protocol = true;
+    config.proxy_header_timeout_secs = 1;
+    let proxy_addr = start_proxy(config).await?;
+
+    // 只发一半的头然后不再发送
+    let mut stream = TcpStream::connect(proxy_addr).await?;
+    stream.write_all(b"PROXY ").await?;
+    let mut buf = Vec::new();
+    let n = timeout(TIMEOUT, stream.read_to_end(&mut buf)).await?.unwrap_or(0);
+    assert_eq!(n, 0);
+    Ok(())
+}
+
+#[tokio::test]
+async fn rejects_denied_clients() -> Result<()> {
+    let mut config = config_for(echo_upstream().await?);
+    config.access.deny = vec!["127.0.0.0/8".parse()?];
+    let proxy_addr = start_proxy(config).await?;
+
+    let mut stream = TcpStream::connect(proxy_addr).await?;
+    let _ = stream.write_all(b"ping").await;
+    let mut buf = Vec::new();
+    let n = timeout(TIMEOUT, stream.read_to_end(&mut buf)).await?.unwrap_or(0);
+    assert_eq!(n, 0);
+    Ok(())
+}
+
+async fn udp_echo_upstream() -> Result<SocketAddr> {
+    let upstream = UdpSocket::bind("127.0.0.1:0").await?;
+    let upstream_addr = upstream.local_addr()?;
+    tokio::spawn(async move {
+        let mut buf = [0u8; 1500];
+        while let Ok((n, addr)) = upstream.recv_from(&mut buf).await {
+            upstream.send_to(&buf[..n], addr).await?;
+        }
+        Ok::<_, io::Error>(())
+    });
+    Ok(upstream_addr)
+}
+
+async fn start_udp_proxy(mut config: ListenerConfig) -> Result<(SocketAddr, Arc<Listener>)> {
+    config.protocol = Protocol::Udp;
+    let socket = UdpSocket::bind("127.0.0.1:0").await?;
+    let proxy_addr = socket.local_addr()?;
+    let listener = Arc::new(Listener::new(config));
+    tokio::spawn(serve_udp(listener.clone(), socket));
+    Ok((proxy_addr, listener))
+}
+
+// 发送一个报文，返回收到的响应，等待 wait 之后还没有响应时返回 None
+async fn udp_ping(client: &UdpSocket, proxy_addr: SocketAddr, msg: &str, wait: Duration) -> Result<Option<String>> {
+    client.send_to(msg.as_bytes(), proxy_addr).await?;
+    let mut buf = [0u8; 1500];
+    match timeout(wait, client.recv(&mut buf)).await {
+        Ok(n) => Ok(Some(String::from_utf8(buf[..n?].to_vec())?)),
+        Err(_) => Ok(None),
+    }
+}
+
+#[tokio::test]
+async fn forwards_udp_datagrams() -> Result<()> {
+    let (proxy_addr, _) = start_udp_proxy(config_for(udp_echo_upstream().await?)).await?;
+
+    // 两个客户端交替发送，响应必须回到各自的客户端
+    let first = UdpSocket::bind("127.0.0.1:0").await?;
+    let second = UdpSocket::bind("127.0.0.1:0").await?;
+    let mut buf = [0u8; 1500];
+    for i in 0..3 {
+        for (name, client) in [("first", &first), ("second", &second)] {
+            let msg = format!("{} {}", name, i);
+            client.send_to(msg.as_bytes(), proxy_addr).await?;
+            let (n, from) = timeout(TIMEOUT, client.recv_from(&mut buf)).await??;
+            assert_eq!(from, proxy_addr);
+            assert_eq!(&buf[..n], msg.as_bytes());
+        }
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn drops_new_udp_clients_over_session_limit() -> Result<()> {
+    let mut config = config_for(udp_echo_upstream().await?);
+    config.max_udp_sessions = 1;
+    let (proxy_addr, _) = start_udp_proxy(config).await?;
+
+    let first = UdpSocket::bind("127.0.0.1:0").await?;
+    let second = UdpSocket::bind("127.0.0.1:0").await?;
+    assert_eq!(udp_ping(&first, proxy_addr, "a", TIMEOUT).await?.as_deref(), Some("a"));
+    assert_eq!(udp_ping(&second, proxy_addr, "b", Duration::from_millis(300)).await?, None);
+    // 已有会话不受影响
+    assert_eq!(udp_ping(&first, proxy_addr, "c", TIMEOUT).await?.as_deref(), Some("c"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn drops_udp_client_denied_after_reload() -> Result<()> {
+    let (proxy_addr, listener) = start_udp_proxy(config_for(udp_echo_upstream().await?)).await?;
+    let client = UdpSocket::bind("127.0.0.1:0").await?;
+    assert_eq!(udp_ping(&client, proxy_addr, "a", TIMEOUT).await?.as_deref(), Some("a"));
+
+    // 和 SIGHUP 重新加载一样替换访问列表，已有的会话也要被拒绝
+    listener.set_access(AccessControl {
+        allow: Vec::new(),
+        deny: vec!["127.0.0.0/8".parse()?],
+    });
+    for msg in ["b", "c"] {
+        assert_eq!(udp_ping(&client, proxy_addr, msg, Duration::from_millis(300)).await?, None);
+    }
+
+    listener.set_access(AccessControl::default());
+    assert_eq!(udp_ping(&client, proxy_addr, "d", TIMEOUT).await?.as_deref(), Some("d"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn closes_upstream_socket_of_removed_udp_session() -> Result<()> {
+    // 上游记下每个报文来自代理的哪个 socket
+    let upstream = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+    let (peers_tx, mut peers) = tokio::sync::mpsc::unbounded_channel();
+    let echo = upstream.clone();
+    tokio::spawn(async move {
+        let mut buf = [0u8; 1500];
+        while let Ok((n, addr)) = echo.recv_from(&mut buf).await {
+            let _ = peers_tx.send(addr);
+            echo.send_to(&buf[..n], addr).await?;
+        }
+        Ok::<_, io::Error>(())
+    });
+    let (proxy_addr, listener) = start_udp_proxy(config_for(upstream.local_addr()?)).await?;
+    let client = UdpSocket::bind("127.0.0.1:0").await?;
+    assert_eq!(udp_ping(&client, proxy_addr, "a", TIMEOUT).await?.as_deref(), Some("a"));
+    let old = peers.recv().await.expect("upstream is running");
+
+    listener.set_access(AccessControl {
+        allow: Vec::new(),
+        deny: vec!["127.0.0.0/8".parse()?],
+    });
+    assert_eq!(udp_ping(&client, proxy_addr, "b", Duration::from_millis(300)).await?, None);
+    listener.set_access(AccessControl::default());
+    assert_eq!(udp_ping(&client, proxy_addr, "c", TIMEOUT).await?.as_deref(), Some("c"));
+    assert_ne!(peers.recv().await, Some(old));
+
+    // 旧会话已经关闭，发给它的报文不能再转给客户端
+    upstream.send_to(b"stale", old).await?;
+    let mut buf = [0u8; 1500];
+    assert!(timeout(Duration::from_millis(300), client.recv(&mut buf)).await.is_err());
+    Ok(())
+}
@@ -0,0 +1,177 @@
+use std::sync::Arc;
+use _04_ecosystem::shortener::{router, AppState, Config, MemoryStorage};
+use anyhow::Result;
+use axum::body::{to_bytes, Body};
+use axum::http::header::{CONTENT_TYPE, LOCATION};
+use axum::http::{Request, StatusCode};
+use axum::response::Response;
+use axum::Router;
+use serde_json::{json, Value};
+use tower::ServiceExt;
+
+const BASE_URL: &str = "http://sho.rt";
+
+fn config() -> Config {
+    Config {
+        base_url: Some(BASE_URL.to_string()),
+        // oneshot 没有客户端地址，所有请求都会落到同一个限流桶里
+        ip_rate_limit: None,
+        ..Config::default()
+    }
+}
+
+fn app() -> Router {
+    router(AppState::new(Arc::new(MemoryStorage::default()), config()))
+}
+
+async fn send(app: &Router, req: Request<Body>) -> Result<(StatusCode, Response)> {
+    let res = app.clone().oneshot(req).await?;
+    Ok((res.status(), res))
+}
+
+async fn json_body(res: Response) -> Result<Value> {
+    let body = to_bytes(res.into_body(), usize::MAX).await?;
+    Ok(serde_json::from_slice(&body)?)
+}
+
+async fn post(app: &Router, body: Value) -> Result<(StatusCode, Value)> {
+    let req = Request::post("/")
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))?;
+    let (status, res) = send(app, req).await?;
+    Ok((status, json_body(res).await?))
+}
+
+// 返回短链接里的 id
+async fn shorten(app: &Router, url: &str) -> Result<String> {
+    let (status, body) = post(app, json!({ "url": url })).await?;
+    assert_eq!(status, StatusCode::CREATED, "{}", body);
+    let short_url = body["url"].as_str().unwrap_or_default();
+    let id = short_url.strip_prefix(&format!("{}/", BASE_URL));
+    Ok(id.expect("short url starts with base_url").to_string())
+}
+
+async fn get(app: &Router, path: &str) -> Result<(StatusCode, Response)> {
+    send(app, Request::get(path).body(Body::empty())?).await
+}
+
+#[tokio::test]
+async fn shorten_returns_short_url() -> Result<()> {
+    let app = app();
+    let id = shorten(&app, "https://example.com/a").await?;
+    assert_eq!(id.len(), Config::default().id_length);
+    Ok(())
+}
+
+#[tokio::test]
+async fn shorten_same_url_returns_same_id() -> Result<()> {
+    let app = app();
+    let first = shorten(&app, "https://example.com/a").await?;
+    assert_eq!(shorten(&app, "https://example.com/a").await?, first);
+    assert_ne!(shorten(&app, "https://example.com/b").await?, first);
+    Ok(())
+}
+
+#[tokio::test]
+async fn shorten_rejects_invalid_url() -> Result<()> {
+    let app = app();
+    let (status, body) = post(&app, json!({ "url": "ftp://example.com/a" })).await?;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["code"], "invalid_input");
+
+    let req = Request::post("/")
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from("not json"))?;
+    let (status, res) = send(&app, req).await?;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(json_body(res).await?["code"], "invalid_request");
+    Ok(())
+}
+
+#[tokio::test]
+async fn shorten_rejects_link_to_itself() -> Result<()> {
+    let app = app();
+    // 换了协议或端口的同一个域名也算
+    for url in ["http://sho.rt/abc", "https://sho.rt/abc", "https://SHO.RT:8443/abc"] {
+        let (status, body) = post(&app, json!({ "url": url })).await?;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", url);
+        assert_eq!(body["code"], "invalid_input");
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn redirect_sets_location() -> Result<()> {
+    let app = app();
+    let id = shorten(&app, "https://example.com/a?b=c").await?;
+    let (status, res) = get(&app, &format!("/{}", id)).await?;
+    assert_eq!(status, StatusCode::PERMANENT_REDIRECT);
+    assert_eq!(res.headers()[LOCATION], "https://example.com/a?b=c");
+    Ok(())
+}
+
+#[tokio::test]
+async fn redirect_uses_link_status() -> Result<()> {
+    let app = app();
+    let body = json!({ "url": "https://example.com/a", "alias": "temp", "redirect_status": 302 });
+    let (status, _) = post(&app, body).await?;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let (status, res) = get(&app, "/temp").await?;
+    assert_eq!(status, StatusCode::FOUND);
+    assert_eq!(res.headers()[LOCATION], "https://example.com/a");
+    Ok(())
+}
+
+#[tokio::test]
+async fn alias_conflicts_with_existing_id() -> Result<()> {
+    let app = app();
+    let body = json!({ "url": "https://example.com/a", "alias": "mine" });
+    assert_eq!(post(&app, body.clone()).await?.0, StatusCode::CREATED);
+    let (status, body) = post(&app, body).await?;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["code"], "conflict");
+    Ok(())
+}
+
+#[tokio::test]
+async fn unknown_id_is_not_found() -> Result<()> {
+    let app = app();
+    let (status, res) = get(&app, "/nope42").await?;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert!(res.headers().get(LOCATION).is_none());
+    let body = json_body(res).await?;
+    assert_eq!(body["code"], "not_found");
+    assert!(body["request_id"].is_string());
+    Ok(())
+}
+
+#[tokio::test]
+async fn used_up_link_is_gone() -> Result<()> {
+    let app = app();
+    let body = json!({ "url": "https://example.com/once", "alias": "once", "max_visits": 1 });
+    assert_eq!(post(&app, body).await?.0, StatusCode::CREATED);
+    assert_eq!(get(&app, "/once").await?.0, StatusCode::PERMANENT_REDIRECT);
+    assert_eq!(get(&app, "/once").await?.0, StatusCode::GONE);
+    Ok(())
+}
+
+#[tokio::test]
+async fn blocked_destination_does_not_use_up_visits() -> Result<()> {
+    let store = Arc::new(MemoryStorage::default());
+    let app = router(AppState::new(store.clone(), config()));
+    let body = json!({ "url": "https://example.com/once", "alias": "once", "max_visits": 1 });
+    assert_eq!(post(&app, body).await?.0, StatusCode::CREATED);
+
+    // 链接创建之后目标域名被封禁
+    let blocked = Config {
+        blocked_domains: vec!["example.com".to_string()],
+        ..config()
+    };
+    let blocked_app = router(AppState::new(store.clone(), blocked));
+    assert_eq!(get(&blocked_app, "/once").await?.0, StatusCode::FORBIDDEN);
+    assert_eq!(get(&blocked_app, "/once").await?.0, StatusCode::FORBIDDEN);
+
+    assert_eq!(get(&app, "/once").await?.0, StatusCode::PERMANENT_REDIRECT);
+    Ok(())
+}